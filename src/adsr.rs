@@ -1,3 +1,26 @@
+use serde::Deserialize;
+
+/// ADSR times and sustain level as written in the input
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ADSRConfig {
+    attack: Option<f64>,
+    decay: Option<f64>,
+    sustain: Option<f64>,
+    release: Option<f64>,
+}
+
+impl ADSRConfig {
+    // Extract ADSR values, defaulting sustain to 1.0, others to 0.0 if not provided
+    pub fn values(&self) -> (f64, f64, f64, f64) {
+        (
+            self.attack.unwrap_or(0.0),
+            self.decay.unwrap_or(0.0),
+            self.sustain.unwrap_or(1.0),
+            self.release.unwrap_or(0.0),
+        )
+    }
+}
+
 pub enum ADSREnvelopeState {
     Attack,
     Decay,
@@ -48,6 +71,17 @@ impl ADSREnvelope {
         }
     }
 
+    // Level of the envelope on its own, between 0.0 and 1.0
+    pub fn level(&mut self, current_sample_index: u32) -> f64 {
+        // A release of 0 seconds divides by zero, the envelope has ended by then
+        let level = self.apply(1.0, current_sample_index);
+        if level.is_nan() {
+            0.0
+        } else {
+            level.clamp(0.0, 1.0)
+        }
+    }
+
     fn determine_state(&self, sample_index: u32) -> ADSREnvelopeState {
         // Release happens if sample index is greater than the duration in seconds
         if sample_index > (self.raw_duration_in_seconds * self.sample_rate as f64) as u32 {
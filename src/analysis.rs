@@ -0,0 +1,120 @@
+use crate::cli::AnyOrchestrator;
+use crate::oscillator::Waveform;
+use crate::voice::SynthVoice;
+
+/// Anything that sounds on the timeline, reduced to what score analysis needs
+pub struct Voice {
+    pub label: String,
+    pub start: f64, // in beats
+    pub end: f64,   // in beats, without the release tail
+    pub amplitude: f64,
+    pub frequency: Option<f64>,     // drum hits have none
+    pub bezier: bool,               // played from the band-limited tables of the Bézier waveform
+    pub instrument: Option<String>, // voice the note plays, `None` for the waveform and drum hits
+    pub release: f64,               // in beats, the tail after `end`
+}
+
+/// Flattens every note and drum hit of a score into voices
+/// Notes of instrument tracks are included with the top-level notes.
+pub fn voices(orchestrator: &AnyOrchestrator) -> Vec<Voice> {
+    match orchestrator {
+        AnyOrchestrator::Regular(regular) => {
+            // Regular notes play one after another
+            let bezier = matches!(regular.waveform(), Waveform::Bezier(_));
+            let mut start = 0.0;
+            regular
+                .notes()
+                .iter()
+                .map(|note| {
+                    let voice = Voice {
+                        label: note.name(regular.tuning()),
+                        start,
+                        end: start + note.beats(),
+                        amplitude: note.amplitude(),
+                        frequency: note.frequency(regular.tuning()).ok(),
+                        bezier,
+                        instrument: None,
+                        release: 0.0,
+                    };
+                    start = voice.end;
+                    voice
+                })
+                .collect()
+        }
+        AnyOrchestrator::Timeline(timeline) => {
+            let seconds_per_beat = 60.0 / timeline.bpm() as f64;
+            let bezier = matches!(timeline.waveform(), Waveform::Bezier(_));
+            let mut voices: Vec<Voice> = timeline
+                .pitched_notes()
+                .map(|(note, voice)| Voice {
+                    label: note.name(timeline.tuning()),
+                    start: note.start_time(),
+                    end: note.start_time() + note.duration(),
+                    amplitude: note.amplitude(),
+                    frequency: note.frequency(timeline.tuning()).ok(),
+                    // Notes with a voice play it instead of the waveform
+                    bezier: bezier && voice.is_none(),
+                    instrument: voice.map(str::to_string),
+                    // Voices with envelopes of their own ring out for their own release
+                    release: voice
+                        .and_then(|name| timeline.voices().get(name))
+                        .and_then(SynthVoice::release)
+                        .unwrap_or(timeline.release())
+                        / seconds_per_beat,
+                })
+                .collect();
+            for track in timeline.tracks() {
+                for hit in track.percussion_notes() {
+                    voices.push(Voice {
+                        label: hit.hit().name().to_string(),
+                        start: hit.start_time(),
+                        end: hit.end_in_seconds(seconds_per_beat) / seconds_per_beat,
+                        amplitude: hit.amplitude(),
+                        frequency: None,
+                        bezier: false,
+                        instrument: None,
+                        release: 0.0,
+                    });
+                }
+            }
+            voices
+        }
+    }
+}
+
+/// State of the mix right after a voice starts or ends
+pub struct MixPoint {
+    pub beat: f64,
+    pub amplitude: f64, // summed amplitude of the voices sounding
+    pub polyphony: usize,
+}
+
+/// Walks the start and end of every voice in time order
+/// Ends come before starts at the same beat, so touching notes don't count as overlapping.
+pub fn sweep(voices: &[Voice]) -> Vec<MixPoint> {
+    let mut events: Vec<(f64, f64)> = Vec::with_capacity(voices.len() * 2);
+    for voice in voices {
+        events.push((voice.start, voice.amplitude));
+        events.push((voice.end, -voice.amplitude));
+    }
+    events.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+
+    let mut amplitude = 0.0;
+    let mut polyphony: usize = 0;
+    events
+        .into_iter()
+        .map(|(beat, change)| {
+            amplitude += change;
+            if change.is_sign_negative() {
+                polyphony = polyphony.saturating_sub(1);
+            } else {
+                polyphony += 1;
+            }
+            MixPoint {
+                beat,
+                amplitude,
+                polyphony,
+            }
+        })
+        .collect()
+}
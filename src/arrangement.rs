@@ -0,0 +1,242 @@
+use crate::errors::OrchestratorError;
+use crate::pattern::{PitchedInstrument, StepPattern};
+use crate::timeline_orchestrator::{TimelineNote, Track};
+use crate::validation::FieldProblem;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::ops::Range;
+
+fn default_repeat() -> u32 {
+    1
+}
+
+/// A reusable part of a song (verse, chorus, ...) with its own local timeline
+/// Note start times inside a section are relative to the start of the section
+#[derive(Debug, Deserialize)]
+pub struct Section {
+    length: Option<f64>, // in beats, defaults to the end of the last note, hit or pattern rounded up to a whole beat
+    #[serde(default)]
+    notes: Vec<TimelineNote>,
+    #[serde(default)]
+    patterns: Vec<StepPattern<PitchedInstrument>>,
+    #[serde(default)]
+    tracks: Vec<Track>,
+    #[serde(skip)]
+    patterns_end: f64, // in beats, end of the pattern grids with their trailing rests
+}
+
+impl Section {
+    pub fn notes(&self) -> &[TimelineNote] {
+        &self.notes
+    }
+
+    pub fn patterns(&self) -> &[StepPattern<PitchedInstrument>] {
+        &self.patterns
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    fn expand_patterns(&mut self) -> Result<(), OrchestratorError> {
+        // Trailing rests leave no notes behind, so the grids are measured first
+        self.patterns_end = self.patterns_end_in_beats();
+        for pattern in self.patterns.drain(..) {
+            self.notes.extend(pattern.expand()?);
+        }
+        for track in self.tracks.iter_mut() {
+            track.expand_patterns()?;
+        }
+        Ok(())
+    }
+
+    // End of the pattern grids with their trailing rests, kept once the patterns are expanded
+    fn patterns_end_in_beats(&self) -> f64 {
+        self.patterns
+            .iter()
+            .map(StepPattern::end_in_beats)
+            .chain(self.tracks.iter().map(Track::patterns_end_in_beats))
+            .fold(self.patterns_end, f64::max)
+    }
+
+    fn length(&self, seconds_per_beat: f64) -> f64 {
+        self.length.unwrap_or_else(|| {
+            let notes_end = self
+                .notes
+                .iter()
+                .map(|note| note.start_time() + note.duration())
+                .fold(0.0, f64::max);
+            let tracks_end = self
+                .tracks
+                .iter()
+                .map(|track| track.end_in_beats(seconds_per_beat))
+                .fold(0.0, f64::max);
+            notes_end
+                .max(tracks_end)
+                .max(self.patterns_end_in_beats())
+                .ceil()
+        })
+    }
+}
+
+/// One entry of the `arrangement` list
+/// Entries play back to back, `offset` shifts an entry (in beats) away from that position
+#[derive(Debug, Deserialize)]
+pub struct ArrangementEntry {
+    section: String,
+    #[serde(default = "default_repeat")]
+    repeat: u32,
+    #[serde(default)]
+    offset: f64,
+}
+
+/// Where a single repetition of a section ended up on the flattened timeline
+#[derive(Debug, Clone)]
+pub struct SectionMarker {
+    pub name: String,
+    pub repetition: u32, // 1-based
+    pub repeat: u32,
+    pub start_time: f64, // in beats
+    pub end_time: f64,   // in beats
+}
+
+impl SectionMarker {
+    pub fn label(&self) -> String {
+        if self.repeat > 1 {
+            format!("{} ({}/{})", self.name, self.repetition, self.repeat)
+        } else {
+            self.name.clone()
+        }
+    }
+
+    pub fn sample_range(&self, seconds_per_beat: f64, sample_rate: u32) -> Range<usize> {
+        let to_sample = |beats: f64| (beats * seconds_per_beat * sample_rate as f64) as usize;
+        to_sample(self.start_time)..to_sample(self.end_time)
+    }
+}
+
+/// The arrangement flattened into a single timeline
+pub struct FlatArrangement {
+    pub notes: Vec<TimelineNote>,
+    pub tracks: Vec<Track>,
+    pub markers: Vec<SectionMarker>,
+}
+
+/// Checks every entry of the arrangement and returns all problems found with the entry's index
+/// Sections are measured the same way with or without their patterns expanded.
+pub fn check(
+    sections: &HashMap<String, Section>,
+    arrangement: &[ArrangementEntry],
+    seconds_per_beat: f64,
+) -> Vec<(usize, FieldProblem)> {
+    let mut problems = Vec::new();
+    // Beat at which the next entry starts
+    let mut cursor: f64 = 0.0;
+
+    for (index, entry) in arrangement.iter().enumerate() {
+        let Some(section) = sections.get(&entry.section) else {
+            problems.push((
+                index,
+                (
+                    "section",
+                    OrchestratorError::UnknownSection(entry.section.clone()),
+                ),
+            ));
+            continue;
+        };
+        if entry.repeat == 0 {
+            problems.push((
+                index,
+                (
+                    "repeat",
+                    OrchestratorError::InvalidArrangement(format!(
+                        "Section '{}' must repeat at least once",
+                        entry.section
+                    )),
+                ),
+            ));
+        }
+
+        let length = section.length(seconds_per_beat);
+        if !length.is_finite() || length <= 0.0 {
+            problems.push((
+                index,
+                (
+                    "section",
+                    OrchestratorError::InvalidArrangement(format!(
+                        "Section '{}' must have a length greater than 0 beats, got {}",
+                        entry.section, length
+                    )),
+                ),
+            ));
+            continue;
+        }
+
+        let entry_start = cursor + entry.offset;
+        if entry_start < 0.0 {
+            problems.push((
+                index,
+                (
+                    "offset",
+                    OrchestratorError::InvalidArrangement(format!(
+                        "Offset {} moves section '{}' before the start of the song",
+                        entry.offset, entry.section
+                    )),
+                ),
+            ));
+        }
+
+        cursor = entry_start + entry.repeat as f64 * length;
+    }
+
+    problems
+}
+
+/// Places every arranged section on the timeline, copying its notes for each repeat
+pub fn flatten(
+    mut sections: HashMap<String, Section>,
+    arrangement: &[ArrangementEntry],
+    seconds_per_beat: f64,
+) -> Result<FlatArrangement, OrchestratorError> {
+    for section in sections.values_mut() {
+        section.expand_patterns()?;
+    }
+    if let Some((_, (_, error))) = check(&sections, arrangement, seconds_per_beat)
+        .into_iter()
+        .next()
+    {
+        return Err(error);
+    }
+
+    let mut flat = FlatArrangement {
+        notes: Vec::new(),
+        tracks: Vec::new(),
+        markers: Vec::new(),
+    };
+    // Beat at which the next entry starts
+    let mut cursor: f64 = 0.0;
+
+    for entry in arrangement {
+        let section = &sections[&entry.section];
+        let length = section.length(seconds_per_beat);
+        let entry_start = cursor + entry.offset;
+        for repetition in 0..entry.repeat {
+            let start_time = entry_start + repetition as f64 * length;
+            flat.notes
+                .extend(section.notes.iter().map(|note| note.shifted(start_time)));
+            flat.tracks
+                .extend(section.tracks.iter().map(|track| track.shifted(start_time)));
+            flat.markers.push(SectionMarker {
+                name: entry.section.clone(),
+                repetition: repetition + 1,
+                repeat: entry.repeat,
+                start_time,
+                end_time: start_time + length,
+            });
+        }
+
+        cursor = entry_start + entry.repeat as f64 * length;
+    }
+
+    Ok(flat)
+}
@@ -0,0 +1,204 @@
+use crate::cli::{get_filename, get_music_input, is_stdio};
+use crate::feedback;
+use crate::wav;
+use anyhow::{Context, Result, anyhow, bail};
+use rayon::prelude::*;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Outcome of rendering one file of a batch
+pub struct BatchResult {
+    pub input_file: PathBuf,
+    pub outcome: Result<RenderStats>,
+}
+
+/// What a successful render produced
+#[derive(Debug, Serialize)]
+pub struct RenderStats {
+    pub path: String, // where the WAV data was written, `-` for stdout
+    pub samples: usize,
+    pub notes: usize,
+    pub duration: f64, // in seconds
+    pub peak: f64,     // largest absolute sample, 1.0 is full scale
+}
+
+impl RenderStats {
+    pub fn new(path: &Path, pcm_samples: &[i16], notes: usize, sample_rate: u32) -> Self {
+        let peak = pcm_samples
+            .iter()
+            .map(|sample| sample.saturating_abs())
+            .max()
+            .unwrap_or(0);
+        RenderStats {
+            path: path.display().to_string(),
+            samples: pcm_samples.len(),
+            notes,
+            duration: pcm_samples.len() as f64 / sample_rate as f64,
+            peak: peak as f64 / i16::MAX as f64,
+        }
+    }
+
+    pub fn peak_dbfs(&self) -> f64 {
+        20.0 * self.peak.log10()
+    }
+}
+
+/// Whether the inputs name a single file, which is rendered on its own instead of as a batch
+pub fn is_single(inputs: &[PathBuf]) -> bool {
+    match inputs {
+        [input] => is_stdio(input) || (!input.is_dir() && !is_pattern(input)),
+        _ => false,
+    }
+}
+
+/// Expands directories and glob patterns into the JSON files they contain
+/// Directories are not searched recursively, paths that exist are kept as they are.
+/// A file named more than once, such as `./x.json` and `x.json`, is kept once.
+pub fn expand_inputs(inputs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if is_stdio(input) {
+            bail!("stdin can only be rendered on its own, not as part of a batch");
+        } else if input.is_dir() {
+            let entries = std::fs::read_dir(input)
+                .context(format!("Failed to read directory: {}", input.display()))?;
+            let mut found: Vec<PathBuf> = entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "json"))
+                .collect();
+            found.sort();
+            files.extend(found);
+        } else if !input.exists() && is_pattern(input) {
+            let pattern = input.to_string_lossy();
+            let mut found = glob::glob(&pattern)
+                .context(format!("Invalid glob pattern: {}", pattern))?
+                .filter_map(|path| path.ok())
+                .filter(|path| path.is_file())
+                .collect::<Vec<_>>();
+            if found.is_empty() {
+                bail!("No files match {}", pattern);
+            }
+            found.sort();
+            files.extend(found);
+        } else {
+            // Missing files show up as failed renders in the summary
+            files.push(input.clone());
+        }
+    }
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.canonicalize().unwrap_or_else(|_| file.clone())));
+    Ok(files)
+}
+
+/// Renders every file in parallel, a failure doesn't stop the others
+/// Each WAV file is named after its input and written into `output_dir`. Inputs with the
+/// same name from different directories would overwrite each other, so they all fail.
+pub fn render_all(files: &[PathBuf], output_dir: &Path, sample_rate: u32) -> Vec<BatchResult> {
+    let output_path = |input_file: &Path| {
+        get_filename(input_file).map(|filename| output_dir.join(format!("{}.wav", filename)))
+    };
+    let mut writers: HashMap<PathBuf, Vec<&PathBuf>> = HashMap::new();
+    for input_file in files {
+        if let Ok(path) = output_path(input_file) {
+            writers.entry(path).or_default().push(input_file);
+        }
+    }
+
+    files
+        .par_iter()
+        .map(|input_file| BatchResult {
+            input_file: input_file.clone(),
+            outcome: output_path(input_file).and_then(|path| match &writers[&path][..] {
+                [_] => render_file(input_file, &path, sample_rate),
+                inputs => Err(collision(inputs, &path)),
+            }),
+        })
+        .collect()
+}
+
+// Error for inputs that would all write the same output file
+fn collision(inputs: &[&PathBuf], output_path: &Path) -> anyhow::Error {
+    let names: Vec<String> = inputs
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    anyhow!(
+        "{} would all write {}, render them separately with their own -o",
+        names.join(", "),
+        output_path.display()
+    )
+}
+
+/// Loads, renders and writes one file without printing any problems it finds
+pub fn render_file(input_file: &Path, output_path: &Path, sample_rate: u32) -> Result<RenderStats> {
+    let orchestrator = get_music_input(input_file, sample_rate)?;
+    let pcm_samples = orchestrator
+        .pcm_samples(sample_rate)
+        .context("Failed to generate PCM samples")?;
+
+    wav::write(
+        &output_path.display().to_string(),
+        &pcm_samples,
+        sample_rate,
+    )
+    .context("Failed to write WAV file")?;
+
+    Ok(RenderStats::new(
+        output_path,
+        &pcm_samples,
+        orchestrator.note_count(),
+        sample_rate,
+    ))
+}
+
+/// Prints one row per file with its duration, peak level and status
+/// When logging JSON, each file is printed as a record instead.
+pub fn print_summary(results: &[BatchResult]) {
+    if feedback::json_log() {
+        for result in results {
+            let record = match &result.outcome {
+                Ok(stats) => json!({
+                    "input": result.input_file,
+                    "status": "ok",
+                    "render": stats,
+                }),
+                Err(error) => json!({
+                    "input": result.input_file,
+                    "status": "failed",
+                    "error": format!("{:#}", error),
+                }),
+            };
+            feedback::record(&record, "");
+        }
+        return;
+    }
+
+    let rows: Vec<Vec<String>> = results
+        .iter()
+        .map(|result| {
+            let file = result.input_file.display().to_string();
+            match &result.outcome {
+                Ok(stats) => vec![
+                    file,
+                    format!("{:.2}s", stats.duration),
+                    format!("{:.1} dBFS", stats.peak_dbfs()),
+                    "ok".to_string(),
+                ],
+                Err(error) => vec![
+                    file,
+                    "-".to_string(),
+                    "-".to_string(),
+                    format!("failed: {}", error.root_cause()),
+                ],
+            }
+        })
+        .collect();
+    feedback::grid(&["FILE", "DURATION", "PEAK", "STATUS"], &rows);
+}
+
+// Whether a path contains glob wildcards
+fn is_pattern(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['])
+}
@@ -0,0 +1,212 @@
+use crate::oscillator::{CycleStats, measure_cycle};
+use crate::wavetable::Wavetable;
+use serde::Deserialize;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+// Newton steps taken to find where a segment reaches a phase, each one at least halves the search
+const SOLVER_STEPS: usize = 24;
+// Phase error small enough to stop solving
+const SOLVER_TOLERANCE: f64 = 1e-10;
+
+/// Shape of one cycle of a Bézier waveform, as given by `control_points`
+/// Either the original four amplitudes at evenly spaced phases, or `[phase, amplitude]`
+/// points describing any number of cubic segments: a start point, then two handles
+/// and an end point for each segment.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(
+    untagged,
+    expecting = "an array of 4 amplitudes or an array of [phase, amplitude] points"
+)]
+pub enum ControlPoints {
+    Amplitudes(Vec<f64>),
+    Points(Vec<[f64; 2]>),
+}
+
+impl ControlPoints {
+    /// Number of cubic segments in one cycle
+    pub fn segments(&self) -> usize {
+        match self {
+            ControlPoints::Amplitudes(_) => 1,
+            ControlPoints::Points(points) => points.len().saturating_sub(1) / 3,
+        }
+    }
+
+    /// Amplitude of the waveform at a phase between 0.0 and 1.0
+    pub fn value(&self, phase: f64) -> f64 {
+        match self {
+            ControlPoints::Amplitudes(points) => {
+                cubic(points[0], points[1], points[2], points[3], phase)
+            }
+            ControlPoints::Points(points) => {
+                // Binary search for the first segment ending after this phase,
+                // zero-width segments are jumps and never play
+                let (mut low, mut high) = (0, self.segments() - 1);
+                while low < high {
+                    let middle = (low + high) / 2;
+                    if points[middle * 3 + 3][0] <= phase {
+                        low = middle + 1;
+                    } else {
+                        high = middle;
+                    }
+                }
+                let segment = &points[low * 3..low * 3 + 4];
+                let t = solve(
+                    [segment[0][0], segment[1][0], segment[2][0], segment[3][0]],
+                    phase,
+                );
+                cubic(
+                    segment[0][1],
+                    segment[1][1],
+                    segment[2][1],
+                    segment[3][1],
+                    t,
+                )
+            }
+        }
+    }
+}
+
+/// Clean-up of a Bézier cycle, set with the top-level `close_loop` and `dc_block` flags
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct Shaping {
+    #[serde(default)]
+    pub close_loop: bool, // tilts the cycle so it ends where it starts
+    #[serde(default)]
+    pub dc_block: bool, // removes the mean of the cycle
+}
+
+/// A Bézier cycle ready to play, with its loop closed and its DC removed when asked
+/// The cleaned-up cycle is scaled down when it would go past -1.0 or 1.0, and rendered
+/// on first use into band-limited tables that every note shares.
+#[derive(Debug, Clone)]
+pub struct BezierWave {
+    control_points: ControlPoints,
+    shaping: Shaping,
+    tilt: f64,   // amplitude removed by the end of the cycle
+    offset: f64, // mean of the tilted cycle, removed when blocking DC
+    gain: f64,
+    table: Arc<OnceLock<Wavetable>>, // shared with clones, so it is only built once
+}
+
+impl BezierWave {
+    pub fn new(control_points: ControlPoints, shaping: Shaping) -> Self {
+        let tilt = if shaping.close_loop {
+            control_points.value(1.0) - control_points.value(0.0)
+        } else {
+            0.0
+        };
+        let tilted = |phase: f64| control_points.value(phase) - tilt * phase;
+        let offset = if shaping.dc_block {
+            measure_cycle(tilted).dc
+        } else {
+            0.0
+        };
+        // An untouched cycle stays within its control points and plays as given
+        let peak = measure_cycle(|phase| tilted(phase) - offset).peak;
+        let gain = if (shaping.close_loop || shaping.dc_block) && peak > 1.0 {
+            1.0 / peak
+        } else {
+            1.0
+        };
+        BezierWave {
+            control_points,
+            shaping,
+            tilt,
+            offset,
+            gain,
+            table: Arc::new(OnceLock::new()),
+        }
+    }
+
+    pub fn control_points(&self) -> &ControlPoints {
+        &self.control_points
+    }
+
+    pub fn shaping(&self) -> Shaping {
+        self.shaping
+    }
+
+    /// Band-limited tables of the cleaned-up waveform, which notes play
+    pub fn table(&self) -> &Wavetable {
+        self.table
+            .get_or_init(|| Wavetable::new(|phase| self.value(phase)))
+    }
+
+    /// Amplitude of the cleaned-up waveform at a phase between 0.0 and 1.0, before band-limiting
+    pub fn value(&self, phase: f64) -> f64 {
+        (self.control_points.value(phase) - self.tilt * phase - self.offset) * self.gain
+    }
+
+    /// DC, RMS and peak of one cycle as it plays
+    pub fn stats(&self) -> CycleStats {
+        measure_cycle(|phase| self.value(phase))
+    }
+}
+
+impl fmt::Display for ControlPoints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlPoints::Amplitudes(points) => write!(
+                f,
+                "[{:.1}, {:.1}, {:.1}, {:.1}]",
+                points[0], points[1], points[2], points[3]
+            ),
+            ControlPoints::Points(points) => {
+                write!(
+                    f,
+                    "{} segments from {} points",
+                    self.segments(),
+                    points.len()
+                )
+            }
+        }
+    }
+}
+
+// Value of a cubic Bézier curve with the given control values at `t`
+fn cubic(p0: f64, p1: f64, p2: f64, p3: f64, t: f64) -> f64 {
+    let one_minus_t = 1.0 - t;
+    one_minus_t.powf(3.0) * p0
+        + 3.0 * one_minus_t.powf(2.0) * t * p1
+        + 3.0 * one_minus_t * t.powf(2.0) * p2
+        + t.powf(3.0) * p3
+}
+
+// Slope of a cubic Bézier curve at `t`
+fn cubic_slope(p0: f64, p1: f64, p2: f64, p3: f64, t: f64) -> f64 {
+    let one_minus_t = 1.0 - t;
+    3.0 * one_minus_t * one_minus_t * (p1 - p0)
+        + 6.0 * one_minus_t * t * (p2 - p1)
+        + 3.0 * t * t * (p3 - p2)
+}
+
+// Finds `t` where the curve's phase reaches `phase`
+// Phases never decrease along a valid segment, so the answer is kept inside a shrinking
+// bracket and Newton steps that leave it fall back to halving it.
+fn solve([x0, x1, x2, x3]: [f64; 4], phase: f64) -> f64 {
+    if x3 <= x0 {
+        return 0.0;
+    }
+    let (mut low, mut high) = (0.0, 1.0);
+    let mut t = ((phase - x0) / (x3 - x0)).clamp(0.0, 1.0);
+    for _ in 0..SOLVER_STEPS {
+        let error = cubic(x0, x1, x2, x3, t) - phase;
+        if error.abs() < SOLVER_TOLERANCE {
+            break;
+        }
+        if error > 0.0 {
+            high = t;
+        } else {
+            low = t;
+        }
+        let slope = cubic_slope(x0, x1, x2, x3, t);
+        let next = t - error / slope;
+        t = if slope > 0.0 && next > low && next < high {
+            next
+        } else {
+            (low + high) / 2.0
+        };
+    }
+    t
+}
@@ -1,42 +1,694 @@
-use crate::feedback;
+use crate::adsr::ADSRConfig;
+use crate::arrangement::{self, ArrangementEntry, Section};
+use crate::bezier::{ControlPoints, Shaping};
+use crate::diagnostics::{self, Checker, JsonPath, PathProblem};
+use crate::errors::OrchestratorError;
+use crate::feedback::{self, Verbosity};
+use crate::fm::FmVoice;
+use crate::include;
 use crate::orchestrator::{Note, Orchestrator};
-use crate::timeline_orchestrator::{TimelineNote, TimelineOrchestrator};
+use crate::oscillator::{Partial, Waveform};
+use crate::pattern::{PitchedInstrument, StepPattern};
+use crate::pluck::PluckVoice;
+use crate::progress::Progress;
+use crate::sampler::{Interpolation, Sampler, Zone, midi_frequency};
+use crate::scala::{KeyboardMap, Scale};
+use crate::soundfont::{self, MAX_BANK, MAX_PRESET};
+use crate::timeline_orchestrator::{TimelineNote, TimelineOrchestrator, Track};
+use crate::tuning::{Pitch, Temperament, Tuning};
+use crate::validation::{
+    FieldProblem, check_adsr, check_morph, check_operator, check_partial, check_pluck_voice,
+    validate_bpm, validate_control_points, validate_frame_size, validate_min_frequency,
+    validate_operator_count, validate_reference_pitch, validate_song_length, validate_zone_count,
+};
+use crate::voice::SynthVoice;
+use crate::wav;
+use crate::wavetable::{Frames, Morph, WavetableWave};
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::error::ErrorKind;
+use clap::{Args as ClapArgs, CommandFactory, Parser, Subcommand, ValueEnum};
 use serde::Deserialize;
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Path that stands for stdin as an input and stdout as an output
+pub const STDIO_PATH: &str = "-";
 
 #[derive(Parser, Debug)]
 #[command(name = "orchestrator-rust")]
 #[command(about = "A WAV file generator from JSON music notation", long_about = None)]
 pub struct Args {
-    /// Path to input JSON file
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    // Rendering is the default, so its arguments can be given without `render`
+    #[command(flatten)]
+    render: Option<RenderArgs>,
+
+    /// How to print problems found in the input
+    #[arg(long, value_enum, global = true, default_value_t = ErrorFormat::Text)]
+    pub error_format: ErrorFormat,
+
+    /// Only print errors, warnings and results
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Also print details about each step
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// How to print messages, `json` logs JSON lines to stderr and results as JSON to stdout
+    #[arg(long, value_enum, global = true, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Render a score to a WAV file
+    Render(RenderArgs),
+    /// Check a score for errors without rendering it
+    Validate(InputArgs),
+    /// Check a score for errors and musical problems without rendering it
+    Lint(InputArgs),
+    /// Summarise a score without rendering it
+    Info(InfoArgs),
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct InputArgs {
+    /// Path to input JSON file (`-` for stdin)
+    #[arg(value_name = "INPUT_FILE")]
+    pub input_file: PathBuf,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct RenderArgs {
+    /// Input JSON files, directories or globs (`-` for stdin)
+    #[arg(value_name = "INPUT_FILE")]
+    pub input_files: Vec<PathBuf>,
+
+    /// Where to write the WAV file (`-` for stdout), defaults to output/<name>.wav
+    /// When rendering several files, the directory to write them into
+    #[arg(short, long, value_name = "OUTPUT")]
+    pub output: Option<PathBuf>,
+
+    /// Keep running and re-render whenever the input or a file it includes changes
+    #[arg(long)]
+    pub watch: bool,
+}
+
+#[derive(ClapArgs, Debug, Clone)]
+pub struct InfoArgs {
+    /// Path to input JSON file (`-` for stdin)
     #[arg(value_name = "INPUT_FILE")]
     pub input_file: PathBuf,
+
+    /// How to print the summary
+    #[arg(long, value_enum, default_value_t = InfoFormat::Table)]
+    pub format: InfoFormat,
+}
+
+impl Args {
+    /// How much to print, from `--quiet` and `--verbose`
+    pub fn verbosity(&self) -> Verbosity {
+        match (self.quiet, self.verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, true) => Verbosity::Verbose,
+            (false, false) => Verbosity::Normal,
+        }
+    }
+
+    /// The subcommand to run, a bare input file is rendered
+    pub fn subcommand(&self) -> Command {
+        let command = match (&self.command, &self.render) {
+            (Some(_), Some(_)) => usage_error(
+                ErrorKind::ArgumentConflict,
+                "input files, --output and --watch go after the subcommand",
+            ),
+            (Some(command), None) => command.clone(),
+            (None, Some(render)) => Command::Render(render.clone()),
+            (None, None) => usage_error(
+                ErrorKind::MissingRequiredArgument,
+                "an INPUT_FILE or a subcommand is required",
+            ),
+        };
+        if let Command::Render(render) = &command
+            && render.input_files.is_empty()
+        {
+            usage_error(
+                ErrorKind::MissingRequiredArgument,
+                "an INPUT_FILE is required",
+            )
+        }
+        command
+    }
+}
+
+// Prints a usage error in clap's format and exits
+fn usage_error(kind: ErrorKind, message: &str) -> ! {
+    <Args as CommandFactory>::command()
+        .error(kind, message)
+        .exit()
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ErrorFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum InfoFormat {
+    Table,
+    Json,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct JSONInput {
     bpm: u8, //beats per min
     notes: Vec<Note>,
-    control_points: Option<Vec<f64>>,
+    control_points: Option<ControlPoints>,
+    #[serde(flatten)]
+    shaping: Shaping,
+    tuning: Option<TuningConfig>,
+    reference_pitch: Option<f64>, // in Hz, the frequency of A4
+    min_frequency: Option<f64>,   // in Hz, the lowest pitch allowed
+    // Only read to point at them, they need the timeline format
+    wavetable: Option<IgnoredAny>,
+    partials: Option<IgnoredAny>,
+    voices: Option<IgnoredAny>,
+}
+
+impl JSONInput {
+    // Every problem the loader would stop at, so they can be reported at once
+    fn check(&self, tuning: &Tuning) -> Vec<PathProblem> {
+        let root = JsonPath::default();
+        let mut checker = Checker::new(tuning, []);
+        check_shared_fields(
+            &mut checker,
+            self.bpm,
+            self.control_points.as_ref(),
+            self.tuning.as_ref(),
+            self.reference_pitch,
+            self.min_frequency,
+        );
+        for (key, set, what) in [
+            ("wavetable", self.wavetable.is_some(), "Wavetables"),
+            ("partials", self.partials.is_some(), "Additive partials"),
+            ("voices", self.voices.is_some(), "Voices"),
+        ] {
+            if set {
+                checker.push(root.key(key), OrchestratorError::TimelineOnly(what));
+            }
+        }
+        if self.notes.is_empty() {
+            checker.push(root.key("notes"), OrchestratorError::EmptyNotes);
+        }
+        checker.notes(&root.key("notes"), &self.notes);
+        checker.into_problems()
+    }
+}
+
+// Checks the top-level fields both input formats have
+fn check_shared_fields(
+    checker: &mut Checker,
+    bpm: u8,
+    control_points: Option<&ControlPoints>,
+    tuning: Option<&TuningConfig>,
+    reference_pitch: Option<f64>,
+    min_frequency: Option<f64>,
+) {
+    let root = JsonPath::default();
+    checker.result(root.key("bpm"), validate_bpm(bpm));
+    if let Some(points) = control_points {
+        checker.result(root.key("control_points"), validate_control_points(points));
+    }
+    if let Some(config) = tuning {
+        checker.result(root.key("tuning"), config.check());
+    }
+    if let Some(reference_pitch) = reference_pitch {
+        checker.result(
+            root.key("reference_pitch"),
+            validate_reference_pitch(reference_pitch),
+        );
+    }
+    if let Some(min_frequency) = min_frequency {
+        checker.result(
+            root.key("min_frequency"),
+            validate_min_frequency(min_frequency, checker.tuning().nyquist()),
+        );
+    }
 }
 
+/// How notes are tuned, set with the top-level `tuning` object
 #[derive(Debug, Deserialize)]
-pub struct ADSRConfig {
-    attack: Option<f64>,
-    decay: Option<f64>,
-    sustain: Option<f64>,
-    release: Option<f64>,
+pub struct TuningConfig {
+    temperament: Option<Temperament>,
+    scale: Option<PathBuf>, // Scala `.scl` file, relative to the file it is written in
+    keyboard_map: Option<PathBuf>, // Scala `.kbm` file, relative to the file it is written in
+}
+
+impl TuningConfig {
+    // A temperament and a scale file would both decide the pitches
+    fn check(&self) -> Result<(), OrchestratorError> {
+        if self.temperament.is_some() && self.scale.is_some() {
+            return Err(OrchestratorError::InvalidTuning(
+                "set either a temperament or a scale file, not both".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    // Reads the scale and keyboard mapping files, relative paths start at `base_dir`
+    fn load(&self, base_dir: &Path) -> Result<Tuning> {
+        let mut tuning = Tuning::default();
+        if let Some(scale) = self.temperament.and_then(Temperament::scale) {
+            tuning = tuning.with_scale(scale);
+        }
+        if let Some(path) = &self.scale {
+            let path = base_dir.join(path);
+            let text = std::fs::read_to_string(&path)
+                .context(format!("Failed to read scale file: {}", path.display()))?;
+            let scale = Scale::parse(&text)
+                .context(format!("Failed to parse scale file: {}", path.display()))?;
+            tuning = tuning.with_scale(scale);
+        }
+        if let Some(path) = &self.keyboard_map {
+            let path = base_dir.join(path);
+            let text = std::fs::read_to_string(&path).context(format!(
+                "Failed to read keyboard mapping: {}",
+                path.display()
+            ))?;
+            let keyboard = KeyboardMap::parse(&text).context(format!(
+                "Failed to parse keyboard mapping: {}",
+                path.display()
+            ))?;
+            tuning = tuning.with_keyboard_map(keyboard);
+        }
+        Ok(tuning)
+    }
+}
+
+/// Wavetable the timeline's notes play, set with the top-level `wavetable` object
+#[derive(Debug, Deserialize)]
+pub struct WavetableConfig {
+    file: PathBuf,             // WAV file, relative to the file it is written in
+    frame_size: Option<usize>, // samples per frame, the whole file is one cycle when unset
+    #[serde(flatten)]
+    morph: Morph,
+}
+
+impl WavetableConfig {
+    // Checks the frame size and how the position moves, the file is checked as it is read
+    fn check(&self, checker: &mut Checker, path: &JsonPath) {
+        if let Some(frame_size) = self.frame_size {
+            checker.result(path.key("frame_size"), validate_frame_size(frame_size));
+        }
+        checker.fields(path, check_morph(&self.morph));
+        if let Some(envelope) = self.morph.envelope {
+            checker.fields(&path.key("envelope"), check_adsr(envelope.adsr.values()));
+        }
+    }
+
+    // Reads the WAV file and cuts it into frames, relative paths start at `base_dir`
+    fn load(&self, base_dir: &Path) -> Result<WavetableWave> {
+        let path = base_dir.join(&self.file);
+        let recording = wav::read(&path)?;
+        let frame_size = self.frame_size.unwrap_or(recording.samples.len());
+        let frames = Frames::from_samples(&recording.samples, frame_size)
+            .context(format!("Failed to load wavetable: {}", path.display()))?;
+        feedback::info(&format!(
+            "Wavetable: {} ({} frame{} of {} samples)",
+            path.display(),
+            frames.count(),
+            if frames.count() == 1 { "" } else { "s" },
+            frame_size
+        ));
+        Ok(WavetableWave::new(frames, self.morph))
+    }
+}
+
+/// A voice as written in the top-level `voices` object, before its files are read
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum VoiceConfig {
+    Fm(FmVoice),
+    Pluck(PluckVoice),
+    Sampler(SamplerConfig),
+    SoundFont(SoundFontConfig),
+}
+
+impl VoiceConfig {
+    // Checks the settings of the voice, its files are checked as they are read
+    fn check(&self, checker: &mut Checker, path: &JsonPath) {
+        match self {
+            VoiceConfig::Fm(voice) => {
+                let operators_path = path.key("operators");
+                checker.result(
+                    operators_path.clone(),
+                    validate_operator_count(voice.operators.len()),
+                );
+                for (index, operator) in voice.operators.iter().enumerate() {
+                    let operator_path = operators_path.index(index);
+                    checker.fields(&operator_path, check_operator(index, operator));
+                    if let Some(envelope) = operator.envelope {
+                        checker.fields(
+                            &operator_path.key("envelope"),
+                            check_adsr(envelope.values()),
+                        );
+                    }
+                }
+            }
+            VoiceConfig::Pluck(voice) => checker.fields(path, check_pluck_voice(voice)),
+            VoiceConfig::Sampler(config) => {
+                let zones_path = path.key("zones");
+                checker.result(zones_path.clone(), validate_zone_count(config.zones.len()));
+                for (index, zone) in config.zones.iter().enumerate() {
+                    checker.fields(&zones_path.index(index), zone.check());
+                }
+            }
+            VoiceConfig::SoundFont(config) => checker.fields(path, config.check()),
+        }
+    }
+
+    // Reads the files of the voice, relative paths start at `base_dir`
+    fn load(self, name: &str, base_dir: &Path) -> Result<SynthVoice> {
+        match self {
+            VoiceConfig::Fm(voice) => Ok(SynthVoice::Fm(voice)),
+            VoiceConfig::Pluck(voice) => Ok(SynthVoice::Pluck(voice)),
+            VoiceConfig::Sampler(config) => {
+                let zones = config
+                    .zones
+                    .iter()
+                    .enumerate()
+                    .map(|(index, zone)| {
+                        zone.load(base_dir)
+                            .context(format!("Failed to load zone {} of voice '{}'", index, name))
+                    })
+                    .collect::<Result<Vec<Zone>>>()?;
+                Ok(SynthVoice::Sampler(Sampler::new(
+                    zones,
+                    config.interpolation,
+                )?))
+            }
+            VoiceConfig::SoundFont(config) => {
+                let path = base_dir.join(&config.file);
+                let preset = soundfont::read(&path, config.bank, config.preset)
+                    .context(format!("Failed to load the SoundFont of voice '{}'", name))?;
+                feedback::info(&format!(
+                    "SoundFont: {} (preset {}:{} '{}', {} zones)",
+                    path.display(),
+                    config.bank,
+                    config.preset,
+                    preset.name,
+                    preset.zones.len()
+                ));
+                Ok(SynthVoice::SoundFont(
+                    Sampler::new(preset.zones, config.interpolation)?.with_layers(),
+                ))
+            }
+        }
+    }
+}
+
+/// Recordings of a sampler voice and how they are resampled
+#[derive(Debug, Deserialize)]
+pub struct SamplerConfig {
+    zones: Vec<ZoneConfig>,
+    #[serde(default)]
+    interpolation: Interpolation,
+}
+
+/// A preset of a SoundFont 2 file, played by a voice
+#[derive(Debug, Deserialize)]
+pub struct SoundFontConfig {
+    file: PathBuf, // SF2 file, relative to the file it is written in
+    preset: u16,   // program number, 0 to 127
+    #[serde(default)]
+    bank: u16, // 0 to 128, General MIDI files keep their drum kits in bank 128
+    #[serde(default)]
+    interpolation: Interpolation,
+}
+
+impl SoundFontConfig {
+    // Checks the preset and bank numbers, the file is checked as it is read
+    fn check(&self) -> Vec<FieldProblem> {
+        let mut problems = Vec::new();
+        if self.preset > MAX_PRESET {
+            problems.push((
+                "preset",
+                OrchestratorError::InvalidSoundFont(format!(
+                    "presets are numbered 0 to {}, got {}",
+                    MAX_PRESET, self.preset
+                )),
+            ));
+        }
+        if self.bank > MAX_BANK {
+            problems.push((
+                "bank",
+                OrchestratorError::InvalidSoundFont(format!(
+                    "banks are numbered 0 to {}, got {}",
+                    MAX_BANK, self.bank
+                )),
+            ));
+        }
+        problems
+    }
+}
+
+/// One WAV file of a sampler voice and the notes it plays
+/// Pitches are in equal temperament with A4 at 440 Hz, as recordings are tuned.
+#[derive(Debug, Deserialize)]
+pub struct ZoneConfig {
+    file: PathBuf,             // WAV file, relative to the file it is written in
+    root: Option<Pitch>,       // pitch of the recording, from the file's smpl chunk when unset
+    low: Option<Pitch>,        // lowest note played, unbounded when unset
+    high: Option<Pitch>,       // highest note played, unbounded when unset
+    loop_start: Option<usize>, // in samples, the file's smpl loop is used when both are unset
+    loop_end: Option<usize>,   // in samples, the first sample after the loop
+}
+
+impl ZoneConfig {
+    // Checks the pitches and loop points, the loop is checked against the file as it is read
+    fn check(&self) -> Vec<FieldProblem> {
+        let tuning = Tuning::default();
+        let mut problems = Vec::new();
+        for (field, pitch) in [
+            ("root", &self.root),
+            ("low", &self.low),
+            ("high", &self.high),
+        ] {
+            if let Some(Err(e)) = pitch.as_ref().map(|pitch| tuning.frequency(pitch)) {
+                problems.push((field, e));
+            }
+        }
+        if let (Some(Ok(low)), Some(Ok(high))) = (
+            self.low.as_ref().map(|pitch| tuning.frequency(pitch)),
+            self.high.as_ref().map(|pitch| tuning.frequency(pitch)),
+        ) && high < low
+        {
+            problems.push((
+                "high",
+                OrchestratorError::InvalidSampler(format!(
+                    "the key range ends at {} below where it starts at {}",
+                    self.high
+                        .as_ref()
+                        .map_or(String::new(), |pitch| pitch.name(&tuning)),
+                    self.low
+                        .as_ref()
+                        .map_or(String::new(), |pitch| pitch.name(&tuning))
+                )),
+            ));
+        }
+        match (self.loop_start, self.loop_end) {
+            (Some(start), Some(end)) if start >= end => problems.push((
+                "loop_end",
+                OrchestratorError::InvalidSampler(format!(
+                    "the loop must end after it starts, got {}..{}",
+                    start, end
+                )),
+            )),
+            (Some(_), None) | (None, Some(_)) => problems.push((
+                "loop_end",
+                OrchestratorError::InvalidSampler(
+                    "set both loop_start and loop_end, or neither".to_string(),
+                ),
+            )),
+            _ => {}
+        }
+        problems
+    }
+
+    // Reads the WAV file, relative paths start at `base_dir`
+    fn load(&self, base_dir: &Path) -> Result<Zone> {
+        let path = base_dir.join(&self.file);
+        let recording = wav::read(&path)?;
+        let tuning = Tuning::default();
+        let pitch = |pitch: &Option<Pitch>| {
+            pitch
+                .as_ref()
+                .map(|pitch| tuning.frequency(pitch))
+                .transpose()
+        };
+        let root = pitch(&self.root)?.or(recording.root_key.map(midi_frequency));
+        let low = pitch(&self.low)?.unwrap_or(0.0);
+        let high = pitch(&self.high)?.unwrap_or(f64::INFINITY);
+        let sample_loop = match (self.loop_start, self.loop_end) {
+            (Some(start), Some(end)) => Some(start..end),
+            _ => recording.sample_loop,
+        };
+        feedback::info(&format!(
+            "Sample: {} ({} samples at {} Hz, {}{})",
+            path.display(),
+            recording.samples.len(),
+            recording.sample_rate,
+            match root {
+                Some(root) => format!("root {:.2} Hz", root),
+                None => "no root, plays at its recorded speed".to_string(),
+            },
+            match &sample_loop {
+                Some(range) => format!(", loops {}..{}", range.start, range.end),
+                None => String::new(),
+            }
+        ));
+        Zone::new(
+            Arc::new(recording.samples),
+            recording.sample_rate,
+            root,
+            low..high,
+            sample_loop,
+        )
+        .context(format!("Failed to load sample: {}", path.display()))
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct TimelineJSONInput {
     bpm: u8, //beats per min
+    #[serde(default)]
     notes: Vec<TimelineNote>,
-    control_points: Option<Vec<f64>>,
+    #[serde(default)]
+    patterns: Vec<StepPattern<PitchedInstrument>>,
+    #[serde(default)]
+    tracks: Vec<Track>,
+    #[serde(default)]
+    sections: HashMap<String, Section>,
+    #[serde(default)]
+    arrangement: Vec<ArrangementEntry>,
+    length: Option<f64>, // in beats, the render is cut or padded to this length
+    control_points: Option<ControlPoints>,
+    #[serde(flatten)]
+    shaping: Shaping,
+    wavetable: Option<WavetableConfig>,
+    partials: Option<Vec<Partial>>, // additive voice, replaces the sine wave
+    #[serde(default)]
+    voices: HashMap<String, VoiceConfig>, // named voices that notes and tracks can play
     #[serde(rename = "adsr")]
     adsr: Option<ADSRConfig>,
+    tuning: Option<TuningConfig>,
+    reference_pitch: Option<f64>, // in Hz, the frequency of A4
+    min_frequency: Option<f64>,   // in Hz, the lowest pitch allowed
+}
+
+impl TimelineJSONInput {
+    // Every problem the loader would stop at, so they can be reported at once
+    fn check(&self, tuning: &Tuning) -> Vec<PathProblem> {
+        let root = JsonPath::default();
+        // Voices count as defined even when broken, so notes playing them aren't reported too
+        let mut checker = Checker::new(tuning, self.voices.keys().map(String::as_str));
+        check_shared_fields(
+            &mut checker,
+            self.bpm,
+            self.control_points.as_ref(),
+            self.tuning.as_ref(),
+            self.reference_pitch,
+            self.min_frequency,
+        );
+        if let Some(length) = self.length {
+            checker.result(root.key("length"), validate_song_length(length));
+        }
+        if let Some(adsr) = &self.adsr {
+            checker.fields(&root.key("adsr"), check_adsr(adsr.values()));
+        }
+        if let Some(config) = &self.wavetable {
+            config.check(&mut checker, &root.key("wavetable"));
+        }
+        if let Some(partials) = &self.partials {
+            let path = root.key("partials");
+            if partials.is_empty() {
+                checker.push(path.clone(), OrchestratorError::EmptyPartials);
+            }
+            for (index, partial) in partials.iter().enumerate() {
+                checker.fields(&path.index(index), check_partial(index, partial));
+            }
+        }
+
+        // Each of these replaces the sine wave, only the first one set can
+        let waveforms = [
+            (
+                "control_points",
+                self.control_points.is_some(),
+                Waveform::BEZIER_NAME,
+            ),
+            (
+                "wavetable",
+                self.wavetable.is_some(),
+                Waveform::WAVETABLE_NAME,
+            ),
+            ("partials", self.partials.is_some(), Waveform::ADDITIVE_NAME),
+        ];
+        if let Some((_, _, first)) = waveforms.iter().find(|(_, set, _)| *set) {
+            for (key, _, _) in waveforms.iter().filter(|(_, set, _)| *set).skip(1) {
+                checker.push(root.key(key), OrchestratorError::ConflictingWaveform(first));
+            }
+        }
+
+        let voices_path = root.key("voices");
+        // Sorted by name so problems are reported in the same order every run
+        let mut voices: Vec<_> = self.voices.iter().collect();
+        voices.sort_by_key(|(name, _)| *name);
+        for (name, config) in voices {
+            config.check(&mut checker, &voices_path.key(name));
+        }
+
+        checker.timeline_body(&root, &self.notes, &self.patterns, &self.tracks);
+        let sections_path = root.key("sections");
+        let mut sections: Vec<_> = self.sections.iter().collect();
+        sections.sort_by_key(|(name, _)| *name);
+        for (name, section) in sections {
+            checker.timeline_body(
+                &sections_path.key(name),
+                section.notes(),
+                section.patterns(),
+                section.tracks(),
+            );
+        }
+        let arrangement_path = root.key("arrangement");
+        for (index, problem) in
+            arrangement::check(&self.sections, &self.arrangement, 60.0 / self.bpm as f64)
+        {
+            checker.fields(&arrangement_path.index(index), vec![problem]);
+        }
+
+        if self.notes.is_empty()
+            && self.patterns.is_empty()
+            && self.tracks.iter().all(Track::is_empty)
+            && self.arrangement.is_empty()
+        {
+            checker.push(root.key("notes"), OrchestratorError::EmptyNotes);
+        }
+        checker.into_problems()
+    }
 }
 
 /// Enum to represent either orchestrator type
@@ -56,10 +708,27 @@ impl AnyOrchestrator {
         }
     }
 
-    pub fn is_bezier(&self) -> bool {
+    /// Same as `pcm_samples`, calling `on_progress` as the render goes on
+    pub fn pcm_samples_with_progress(
+        &self,
+        sample_rate: u32,
+        on_progress: &mut dyn FnMut(&Progress),
+    ) -> Result<Vec<i16>, crate::errors::OrchestratorError> {
         match self {
-            AnyOrchestrator::Regular(orch) => orch.is_bezier(),
-            AnyOrchestrator::Timeline(orch) => orch.is_bezier(),
+            AnyOrchestrator::Regular(orch) => {
+                orch.pcm_samples_with_progress(sample_rate, on_progress)
+            }
+            AnyOrchestrator::Timeline(orch) => {
+                orch.pcm_samples_with_progress(sample_rate, on_progress)
+            }
+        }
+    }
+
+    /// Waveform the pitched notes play
+    pub fn waveform(&self) -> Waveform {
+        match self {
+            AnyOrchestrator::Regular(orch) => orch.waveform(),
+            AnyOrchestrator::Timeline(orch) => orch.waveform(),
         }
     }
 
@@ -69,13 +738,29 @@ impl AnyOrchestrator {
             AnyOrchestrator::Timeline(orch) => orch.note_count(),
         }
     }
+
+    /// Label and sample range of every arranged section (timeline only)
+    pub fn section_ranges(&self, sample_rate: u32) -> Vec<(String, Range<usize>)> {
+        match self {
+            AnyOrchestrator::Regular(_) => Vec::new(),
+            AnyOrchestrator::Timeline(orch) => orch.section_ranges(sample_rate),
+        }
+    }
 }
 
 pub fn parse_args() -> Args {
     Args::parse()
 }
 
+/// Whether a path stands for stdin or stdout
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new(STDIO_PATH)
+}
+
 pub fn get_filename(filepath: &Path) -> Result<String> {
+    if is_stdio(filepath) {
+        return Ok("stdin".to_string());
+    }
     let filename = filepath
         .file_stem()
         .context("Failed to extract filename from path")?
@@ -84,13 +769,30 @@ pub fn get_filename(filepath: &Path) -> Result<String> {
     Ok(filename.to_string())
 }
 
-pub fn get_music_input(filepath: &Path) -> Result<AnyOrchestrator> {
-    let input_data = std::fs::read_to_string(filepath)
-        .context(format!("Failed to read input file: {}", filepath.display()))?;
+/// Loads a score for rendering at `sample_rate`, which limits the pitches notes may have
+pub fn get_music_input(filepath: &Path, sample_rate: u32) -> Result<AnyOrchestrator> {
+    let input_data = if is_stdio(filepath) {
+        std::io::read_to_string(std::io::stdin()).context("Failed to read input from stdin")?
+    } else {
+        std::fs::read_to_string(filepath)
+            .context(format!("Failed to read input file: {}", filepath.display()))?
+    };
 
     // First, parse as a generic JSON value to check the timeline field
-    let json_value: serde_json::Value =
-        serde_json::from_str(&input_data).context("Failed to parse JSON input file")?;
+    let json_value: Value = serde_json::from_str(&input_data)
+        .map_err(|e| OrchestratorError::InvalidInput(vec![diagnostics::from_syntax_error(&e)]))
+        .context("Failed to parse JSON input file")?;
+    // Pull in any other files the score includes
+    let resolved = if is_stdio(filepath) {
+        include::resolve_includes_in(json_value, Path::new(""))
+    } else {
+        include::resolve_includes(json_value, filepath)
+    }
+    .context("Failed to resolve included files")?;
+    for file in &resolved.files {
+        feedback::debug(&format!("Included {}", file.display()));
+    }
+    let json_value = resolved.value;
 
     // Check if timeline field exists and is true
     let is_timeline = json_value
@@ -101,18 +803,57 @@ pub fn get_music_input(filepath: &Path) -> Result<AnyOrchestrator> {
     if is_timeline {
         feedback::info("Using timeline orchestrator");
         // Parse as timeline input
-        let timeline_input: TimelineJSONInput = serde_json::from_value(json_value)
-            .context("Failed to parse timeline JSON input - ensure notes have 'start_time' and 'duration' fields")?;
+        let mut timeline_input: TimelineJSONInput = parse_input(json_value, &input_data)?;
+        // Load the tuning first, note pitches are checked against it
+        let tuning = load_tuning(
+            timeline_input.tuning.as_ref(),
+            timeline_input.reference_pitch,
+            timeline_input.min_frequency,
+            filepath,
+            sample_rate,
+        )?;
+        reject_problems(timeline_input.check(&tuning), &input_data)?;
 
-        // Extract ADSR values, defaulting sustain to 1.0, others to 0.0 if not provided
-        let adsr_values = timeline_input.adsr.as_ref().map(|adsr| {
-            (
-                adsr.attack.unwrap_or(0.0),
-                adsr.decay.unwrap_or(0.0),
-                adsr.sustain.unwrap_or(1.0),
-                adsr.release.unwrap_or(0.0),
+        // Expand step patterns into regular timeline notes
+        if !timeline_input.patterns.is_empty() {
+            feedback::info(&format!(
+                "Expanding {} step patterns",
+                timeline_input.patterns.len()
+            ));
+        }
+        for pattern in timeline_input.patterns.drain(..) {
+            timeline_input
+                .notes
+                .extend(pattern.expand().context("Failed to expand step pattern")?);
+        }
+        for track in timeline_input.tracks.iter_mut() {
+            track
+                .expand_patterns()
+                .context("Failed to expand track step patterns")?;
+        }
+
+        // Flatten the song structure into the timeline
+        let mut section_markers = Vec::new();
+        if !timeline_input.arrangement.is_empty() {
+            let flat = arrangement::flatten(
+                std::mem::take(&mut timeline_input.sections),
+                &timeline_input.arrangement,
+                60.0 / timeline_input.bpm as f64,
             )
-        });
+            .context("Failed to arrange song sections")?;
+            feedback::info(&format!(
+                "Arrangement: {} sections placed ({} notes)",
+                flat.markers.len(),
+                flat.notes.len() + flat.tracks.iter().map(Track::note_count).sum::<usize>()
+            ));
+            timeline_input.notes.extend(flat.notes);
+            timeline_input.tracks.extend(flat.tracks);
+            section_markers = flat.markers;
+        } else if !timeline_input.sections.is_empty() {
+            feedback::info("Sections defined but no arrangement given, sections will not play");
+        }
+
+        let adsr_values = timeline_input.adsr.as_ref().map(ADSRConfig::values);
 
         // Log ADSR configuration
         if let Some((attack, decay, sustain, release)) = adsr_values {
@@ -128,34 +869,85 @@ pub fn get_music_input(filepath: &Path) -> Result<AnyOrchestrator> {
 
         // Log control points if present
         if let Some(ref points) = timeline_input.control_points {
+            feedback::info(&format!("Bezier control points: {}", points));
+            log_shaping(timeline_input.shaping);
+        }
+
+        if let Some(ref partials) = timeline_input.partials {
+            feedback::info(&format!("Additive partials: {}", partials.len()));
+        }
+
+        let mut voices = HashMap::new();
+        for (name, config) in timeline_input.voices.drain() {
+            let voice = config.load(&name, base_dir(filepath))?;
+            voices.insert(name, voice);
+        }
+        if !voices.is_empty() {
+            let mut names: Vec<String> = voices
+                .iter()
+                .map(|(name, voice)| format!("{} ({})", name, voice.kind()))
+                .collect();
+            names.sort();
+            feedback::info(&format!("Voices: {}", names.join(", ")));
+        }
+
+        let wavetable = match &timeline_input.wavetable {
+            Some(config) => Some(config.load(base_dir(filepath))?),
+            None => None,
+        };
+
+        if !timeline_input.tracks.is_empty() {
             feedback::info(&format!(
-                "Bezier control points: [{:.1}, {:.1}, {:.1}, {:.1}]",
-                points[0], points[1], points[2], points[3]
+                "Extra tracks: {} ({} notes)",
+                timeline_input.tracks.len(),
+                timeline_input
+                    .tracks
+                    .iter()
+                    .map(Track::note_count)
+                    .sum::<usize>()
             ));
         }
 
         let orchestrator = TimelineOrchestrator::new(
             timeline_input.bpm,
             timeline_input.notes,
+            timeline_input.tracks,
             timeline_input.control_points,
             adsr_values,
         )
         .map_err(|e| anyhow::anyhow!(e))
-        .context("Failed to create timeline orchestrator from input")?;
+        .context("Failed to create timeline orchestrator from input")?
+        .with_sections(section_markers)
+        .with_shaping(timeline_input.shaping)
+        .with_wavetable(wavetable)
+        .context("Failed to set the wavetable")?
+        .with_partials(timeline_input.partials)
+        .context("Failed to set the partials")?
+        .with_voices(voices)
+        .context("Failed to set up the voices")?
+        .with_length(timeline_input.length)
+        .context("Failed to set the song length")?
+        .with_tuning(tuning)
+        .context("Failed to tune the notes")?;
 
         Ok(AnyOrchestrator::Timeline(orchestrator))
     } else {
         feedback::info("Using regular orchestrator");
         // Parse as regular input
-        let orchestrator_input: JSONInput = serde_json::from_value(json_value)
-            .context("Failed to parse JSON input - ensure notes have 'beats' field")?;
+        let orchestrator_input: JSONInput = parse_input(json_value, &input_data)?;
+        let tuning = load_tuning(
+            orchestrator_input.tuning.as_ref(),
+            orchestrator_input.reference_pitch,
+            orchestrator_input.min_frequency,
+            filepath,
+            sample_rate,
+        )?;
+        reject_problems(orchestrator_input.check(&tuning), &input_data)?;
 
         // Log control points if present
         if let Some(ref points) = orchestrator_input.control_points {
-            feedback::info(&format!(
-                "Bezier control points: [{:.1}, {:.1}, {:.1}, {:.1}]",
-                points[0], points[1], points[2], points[3]
-            ));
+            feedback::info(&format!("Bezier control points: {}", points));
+            log_shaping(orchestrator_input.shaping);
         }
 
         let orchestrator = Orchestrator::new(
@@ -164,8 +956,96 @@ pub fn get_music_input(filepath: &Path) -> Result<AnyOrchestrator> {
             orchestrator_input.control_points,
         )
         .map_err(|e| anyhow::anyhow!(e))
-        .context("Failed to create orchestrator from input")?;
+        .context("Failed to create orchestrator from input")?
+        .with_shaping(orchestrator_input.shaping)
+        .with_tuning(tuning)
+        .context("Failed to tune the notes")?;
 
         Ok(AnyOrchestrator::Regular(orchestrator))
     }
 }
+
+// Deserializes the input, a malformed value stops it before the other values are checked
+fn parse_input<T: DeserializeOwned>(document: Value, source: &str) -> Result<T> {
+    serde_path_to_error::deserialize(document)
+        .map_err(|e| {
+            OrchestratorError::InvalidInput(vec![diagnostics::from_type_error(&e, source)])
+        })
+        .context("Input validation failed")
+}
+
+// Reports every problem the checks found at once, with where each one is in `source`
+fn reject_problems(problems: Vec<PathProblem>, source: &str) -> Result<()> {
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(OrchestratorError::InvalidInput(diagnostics::diagnose(
+        problems, source,
+    )))
+    .context("Input validation failed")
+}
+
+// Builds the tuning from the input's `tuning`, `reference_pitch` and `min_frequency`
+// Invalid values are reported by the checks, notes are checked against the defaults
+// until they are fixed.
+fn load_tuning(
+    config: Option<&TuningConfig>,
+    reference_pitch: Option<f64>,
+    min_frequency: Option<f64>,
+    filepath: &Path,
+    sample_rate: u32,
+) -> Result<Tuning> {
+    let mut tuning = match config.filter(|config| config.check().is_ok()) {
+        Some(config) => {
+            let tuning = config
+                .load(base_dir(filepath))
+                .context("Failed to load the tuning")?;
+            feedback::info(&format!(
+                "Tuning: {} ({} notes per octave)",
+                tuning.name(),
+                tuning.keys_per_octave()
+            ));
+            tuning
+        }
+        None => Tuning::default(),
+    }
+    .with_sample_rate(sample_rate);
+
+    if let Some(reference_pitch) = reference_pitch
+        && validate_reference_pitch(reference_pitch).is_ok()
+    {
+        tuning = tuning.with_reference_pitch(reference_pitch)?;
+        feedback::info(&format!(
+            "Reference pitch: {} = {} Hz",
+            tuning.reference_note(),
+            reference_pitch
+        ));
+    }
+    if let Some(min_frequency) = min_frequency
+        && validate_min_frequency(min_frequency, tuning.nyquist()).is_ok()
+    {
+        tuning = tuning.with_min_frequency(min_frequency)?;
+        feedback::info(&format!("Lowest pitch allowed: {} Hz", min_frequency));
+    }
+    Ok(tuning)
+}
+
+// Directory that relative paths in the input start from, the working directory for stdin
+// Paths from included files were made relative to their own file while resolving them.
+fn base_dir(filepath: &Path) -> &Path {
+    if is_stdio(filepath) {
+        Path::new("")
+    } else {
+        filepath.parent().unwrap_or(Path::new(""))
+    }
+}
+
+// Logs the clean-up applied to the Bezier waveform
+fn log_shaping(shaping: Shaping) {
+    if shaping.close_loop {
+        feedback::info("Bezier loop: closed, each cycle ends where it starts");
+    }
+    if shaping.dc_block {
+        feedback::info("Bezier DC blocking: on, the mean of each cycle is removed");
+    }
+}
@@ -0,0 +1,440 @@
+use crate::errors::OrchestratorError;
+use crate::include::INCLUDE_KEY;
+use crate::orchestrator::Note;
+use crate::pattern::{PitchedInstrument, StepPattern};
+use crate::timeline_orchestrator::{TimelineNote, Track};
+use crate::tuning::{Pitch, Tuning};
+use crate::validation::{
+    FieldProblem, check_note, check_percussion_note, check_timeline_note, validate_pitch,
+};
+use serde::Serialize;
+use std::fmt;
+
+/// A single problem found in an input file
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    pub path: String, // e.g. `notes[37].amplitude`
+    pub message: String,
+    pub line: Option<usize>, // 1-based, in the top-level input file, unset for included values
+    pub column: Option<usize>,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => {
+                write!(f, "{}:{} {}: {}", line, column, self.path, self.message)
+            }
+            _ => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Location of a value inside a JSON document
+#[derive(Debug, Clone, Default)]
+pub struct JsonPath(Vec<PathSegment>);
+
+impl JsonPath {
+    pub fn key(&self, key: &str) -> Self {
+        let mut path = self.clone();
+        path.0.push(PathSegment::Key(key.to_string()));
+        path
+    }
+
+    pub fn index(&self, index: usize) -> Self {
+        let mut path = self.clone();
+        path.0.push(PathSegment::Index(index));
+        path
+    }
+
+    // Places the problems of the fields of the value at this path
+    fn fields(&self, problems: Vec<FieldProblem>) -> impl Iterator<Item = PathProblem> + '_ {
+        problems
+            .into_iter()
+            .map(|(field, error)| (self.key(field), error))
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, "<root>");
+        }
+        for (position, segment) in self.0.iter().enumerate() {
+            match segment {
+                PathSegment::Key(key) if position == 0 => write!(f, "{}", key)?,
+                PathSegment::Key(key) => write!(f, ".{}", key)?,
+                PathSegment::Index(index) => write!(f, "[{}]", index)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A problem found by the loader's checks and the value it was found in
+pub type PathProblem = (JsonPath, OrchestratorError);
+
+/// Turns checked problems into diagnostics with the line and column of their value
+/// `source` is the text of the top-level file and is only used to find line numbers;
+/// problems in values pulled in through includes, or in array elements after a spliced
+/// include, have none.
+pub fn diagnose(problems: Vec<PathProblem>, source: &str) -> Vec<Diagnostic> {
+    problems
+        .into_iter()
+        .map(|(path, error)| diagnostic(&path, error, source))
+        .collect()
+}
+
+/// Turns a JSON syntax error into a diagnostic at the position serde_json reported
+pub fn from_syntax_error(error: &serde_json::Error) -> Diagnostic {
+    Diagnostic {
+        path: JsonPath::default().to_string(),
+        message: error.to_string(),
+        line: Some(error.line()),
+        column: Some(error.column()),
+    }
+}
+
+/// Turns a type error into a diagnostic at the value that failed to deserialize
+/// Deserializing stops there, so the checks of the other values only run once it is fixed.
+pub fn from_type_error(
+    error: &serde_path_to_error::Error<serde_json::Error>,
+    source: &str,
+) -> Diagnostic {
+    let mut path = JsonPath::default();
+    for segment in error.path() {
+        path = match segment {
+            serde_path_to_error::Segment::Map { key } => path.key(key),
+            serde_path_to_error::Segment::Seq { index } => path.index(*index),
+            // Tags and flattened fields don't appear in the text
+            _ => path,
+        };
+    }
+    diagnostic(&path, error.inner(), source)
+}
+
+fn diagnostic(path: &JsonPath, message: impl fmt::Display, source: &str) -> Diagnostic {
+    let position = locate(source, path);
+    Diagnostic {
+        path: path.to_string(),
+        message: message.to_string(),
+        line: position.map(|(line, _)| line),
+        column: position.map(|(_, column)| column),
+    }
+}
+
+/// Runs the loader's checks over notes, patterns and tracks and keeps where each problem is
+/// Pitches are checked against `tuning`, voice names against the names in `voices`.
+pub struct Checker<'a> {
+    tuning: &'a Tuning,
+    voices: Vec<&'a str>,
+    problems: Vec<PathProblem>,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(tuning: &'a Tuning, voices: impl IntoIterator<Item = &'a str>) -> Self {
+        Checker {
+            tuning,
+            voices: voices.into_iter().collect(),
+            problems: Vec::new(),
+        }
+    }
+
+    pub fn tuning(&self) -> &Tuning {
+        self.tuning
+    }
+
+    pub fn push(&mut self, path: JsonPath, error: OrchestratorError) {
+        self.problems.push((path, error));
+    }
+
+    // Keeps the error of a fail-fast check of the value at `path`
+    pub fn result(&mut self, path: JsonPath, result: Result<(), OrchestratorError>) {
+        if let Err(e) = result {
+            self.push(path, e);
+        }
+    }
+
+    pub fn fields(&mut self, path: &JsonPath, problems: Vec<FieldProblem>) {
+        self.problems.extend(path.fields(problems));
+    }
+
+    pub fn into_problems(self) -> Vec<PathProblem> {
+        self.problems
+    }
+
+    // Reports a note that has no pitch in the tuning or one that can't be played
+    fn pitch(&mut self, path: &JsonPath, pitch: &Pitch) {
+        if let Err(e) = validate_pitch(pitch, self.tuning) {
+            let field = match (&e, pitch.is_raw()) {
+                (_, true) => "frequency",
+                (OrchestratorError::InvalidFrequency { .. }, false) => "octave",
+                _ => "id",
+            };
+            self.push(path.key(field), e);
+        }
+    }
+
+    // Reports a note or track naming a voice that isn't defined
+    fn voice(&mut self, path: &JsonPath, voice: Option<&str>) {
+        if let Some(name) = voice
+            && !self.voices.contains(&name)
+        {
+            self.push(
+                path.key("voice"),
+                OrchestratorError::UnknownVoice(name.to_string()),
+            );
+        }
+    }
+
+    /// Checks the notes of the regular format
+    pub fn notes(&mut self, path: &JsonPath, notes: &[Note]) {
+        for (index, note) in notes.iter().enumerate() {
+            let note_path = path.index(index);
+            self.fields(&note_path, check_note(index, note));
+            self.pitch(&note_path, note.pitch());
+        }
+    }
+
+    /// Checks the notes, patterns and tracks of the timeline or of a section
+    pub fn timeline_body(
+        &mut self,
+        path: &JsonPath,
+        notes: &[TimelineNote],
+        patterns: &[StepPattern<PitchedInstrument>],
+        tracks: &[Track],
+    ) {
+        self.pitched_notes(path, notes, patterns);
+
+        let tracks_path = path.key("tracks");
+        for (index, track) in tracks.iter().enumerate() {
+            let track_path = tracks_path.index(index);
+            match track {
+                Track::Percussion(track) => {
+                    let notes_path = track_path.key("notes");
+                    for (index, note) in track.notes().iter().enumerate() {
+                        self.fields(&notes_path.index(index), check_percussion_note(index, note));
+                    }
+                    let patterns_path = track_path.key("patterns");
+                    for (index, pattern) in track.patterns().iter().enumerate() {
+                        self.fields(&patterns_path.index(index), pattern.check_steps());
+                    }
+                }
+                Track::Instrument(track) => {
+                    self.voice(&track_path, track.voice());
+                    self.pitched_notes(&track_path, track.notes(), track.patterns());
+                }
+            }
+        }
+    }
+
+    // Checks the pitched notes and patterns of a body or an instrument track
+    fn pitched_notes(
+        &mut self,
+        path: &JsonPath,
+        notes: &[TimelineNote],
+        patterns: &[StepPattern<PitchedInstrument>],
+    ) {
+        let notes_path = path.key("notes");
+        for (index, note) in notes.iter().enumerate() {
+            let note_path = notes_path.index(index);
+            self.fields(&note_path, check_timeline_note(index, note));
+            self.pitch(&note_path, note.pitch());
+            self.voice(&note_path, note.voice());
+        }
+
+        let patterns_path = path.key("patterns");
+        for (index, pattern) in patterns.iter().enumerate() {
+            let pattern_path = patterns_path.index(index);
+            self.fields(&pattern_path, pattern.check());
+            self.pitch(&pattern_path, pattern.pitch());
+        }
+    }
+}
+
+/// Finds the 1-based line and column where the value at `path` starts in `source`
+/// Falls back to the deepest parent that exists in the text, and gives `None` when the
+/// path leads into an included value, whose text is in another file.
+pub fn locate(source: &str, path: &JsonPath) -> Option<(usize, usize)> {
+    let mut scanner = Scanner {
+        bytes: source.as_bytes(),
+        position: 0,
+        through_include: false,
+    };
+    scanner.skip_whitespace();
+    let offset = scanner.find(&path.0)?;
+    if scanner.through_include {
+        return None;
+    }
+
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let column = before[line_start..].chars().count() + 1;
+    Some((line, column))
+}
+
+/// Minimal JSON scanner that only tracks where values start
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    through_include: bool, // the path left the text for an included file
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.position).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(b' ' | b'\n' | b'\r' | b'\t')) {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.skip_whitespace();
+        if self.peek()? != byte {
+            return None;
+        }
+        self.position += 1;
+        self.skip_whitespace();
+        Some(())
+    }
+
+    // Descends into the value at the current position following `path`
+    fn find(&mut self, path: &[PathSegment]) -> Option<usize> {
+        let start = self.position;
+        let Some((segment, rest)) = path.split_first() else {
+            return Some(start);
+        };
+
+        let found = match (segment, self.peek()?) {
+            (PathSegment::Key(key), b'{') => self.find_member(key, rest),
+            (PathSegment::Index(index), b'[') => self.find_element(*index, rest),
+            _ => None,
+        };
+        Some(found.unwrap_or(start))
+    }
+
+    fn find_member(&mut self, key: &str, rest: &[PathSegment]) -> Option<usize> {
+        self.expect(b'{')?;
+        let mut included = false;
+        while self.peek()? != b'}' {
+            let name = self.string()?;
+            self.expect(b':')?;
+            if name == key {
+                return self.find(rest);
+            }
+            included |= name == INCLUDE_KEY;
+            self.skip_value()?;
+            self.skip_whitespace();
+            if self.peek()? == b',' {
+                self.expect(b',')?;
+            }
+        }
+        // Keys missing from an object with an include come from the included file
+        self.through_include = included;
+        None
+    }
+
+    fn find_element(&mut self, index: usize, rest: &[PathSegment]) -> Option<usize> {
+        self.expect(b'[')?;
+        let mut current = 0;
+        while self.peek()? != b']' {
+            // A spliced include may hold any number of elements, shifting the later ones
+            if self.is_bare_include() {
+                self.through_include = true;
+                return None;
+            }
+            if current == index {
+                return self.find(rest);
+            }
+            self.skip_value()?;
+            self.skip_whitespace();
+            if self.peek()? == b',' {
+                self.expect(b',')?;
+            }
+            current += 1;
+        }
+        None
+    }
+
+    // Whether the value at the current position is an object holding only an include
+    fn is_bare_include(&mut self) -> bool {
+        let start = self.position;
+        let bare = (|| {
+            self.expect(b'{')?;
+            let name = self.string()?;
+            self.expect(b':')?;
+            self.skip_value()?;
+            self.skip_whitespace();
+            Some(name == INCLUDE_KEY && self.peek()? == b'}')
+        })()
+        .unwrap_or(false);
+        self.position = start;
+        bare
+    }
+
+    // Reads a string, keeping escaped characters as-is apart from the backslash
+    fn string(&mut self) -> Option<String> {
+        if self.peek()? != b'"' {
+            return None;
+        }
+        self.position += 1;
+        let mut bytes = Vec::new();
+        loop {
+            match self.peek()? {
+                b'"' => break,
+                b'\\' => {
+                    self.position += 1;
+                    bytes.push(self.peek()?);
+                }
+                byte => bytes.push(byte),
+            }
+            self.position += 1;
+        }
+        self.position += 1;
+        String::from_utf8(bytes).ok()
+    }
+
+    fn skip_value(&mut self) -> Option<()> {
+        match self.peek()? {
+            b'"' => {
+                self.string()?;
+            }
+            open @ (b'{' | b'[') => {
+                let close = if open == b'{' { b'}' } else { b']' };
+                self.expect(open)?;
+                while self.peek()? != close {
+                    if open == b'{' {
+                        self.string()?;
+                        self.expect(b':')?;
+                    }
+                    self.skip_value()?;
+                    self.skip_whitespace();
+                    if self.peek()? == b',' {
+                        self.expect(b',')?;
+                    }
+                }
+                self.position += 1;
+            }
+            _ => {
+                // Numbers, booleans and null run until the next delimiter
+                while !matches!(
+                    self.peek()?,
+                    b',' | b']' | b'}' | b' ' | b'\n' | b'\r' | b'\t'
+                ) {
+                    self.position += 1;
+                }
+            }
+        }
+        Some(())
+    }
+}
@@ -1,23 +1,142 @@
+use crate::diagnostics::Diagnostic;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum OrchestratorError {
-    #[error("Invalid note ID: {0}. Note ID must be between 0 and 11 (12 chromatic notes)")]
-    InvalidNoteId(u8),
+    #[error(
+        "Invalid note ID: {id}. Note ID must be between 0 and {} ({keys} notes per octave)",
+        keys - 1
+    )]
+    InvalidNoteId { id: u8, keys: usize },
+
+    #[error("Note {id} in octave {octave} has no pitch, the keyboard mapping leaves it unmapped")]
+    UnmappedNote { id: u8, octave: u8 },
 
     #[error("Invalid BPM: {0}. BPM must be between 1 and 255")]
     InvalidBpm(u8),
 
-    #[error("Invalid octave: {0}. Octave must be between 0 and 8")]
-    InvalidOctave(u8),
+    #[error("Missing pitch. A note needs an `id` and an `octave`, or a `frequency`")]
+    MissingPitch,
+
+    #[error("Conflicting pitch. A note with a `frequency` can't also have an `id` or `octave`")]
+    ConflictingPitch,
+
+    #[error(
+        "Invalid pitch: {frequency:.2} Hz. Pitches must be at least {floor} Hz and below the Nyquist frequency of {nyquist} Hz"
+    )]
+    InvalidFrequency {
+        frequency: f64,
+        floor: f64,
+        nyquist: f64,
+    },
+
+    #[error(
+        "Invalid minimum frequency: {0}. The floor must be a finite number of Hz greater than 0 and below Nyquist"
+    )]
+    InvalidMinFrequency(f64),
 
     #[error("Invalid amplitude: {0}. Amplitude must be between 0.0 and 1.0")]
     InvalidAmplitude(f64),
 
+    #[error("Invalid cents offset: {0}. Cents must be a finite number")]
+    InvalidCents(f64),
+
+    #[error(
+        "Invalid reference pitch: {0}. The frequency of A4 must be a finite number of Hz greater than 0"
+    )]
+    InvalidReferencePitch(f64),
+
+    #[error("Invalid tuning: {0}")]
+    InvalidTuning(String),
+
+    #[error("Invalid {field} for note {index}: {value}. {reason}")]
+    InvalidNoteTiming {
+        index: usize,
+        field: &'static str,
+        value: f64,
+        reason: &'static str,
+    },
+
+    #[error("Invalid ADSR {field}: {value}. {reason}")]
+    InvalidEnvelope {
+        field: &'static str,
+        value: f64,
+        reason: &'static str,
+    },
+
+    #[error("Invalid song length: {0}. Length must be a finite number of beats greater than 0")]
+    InvalidSongLength(f64),
+
     #[error("No notes provided. At least one note is required")]
     EmptyNotes,
 
     #[error("Invalid control points: {0}")]
     InvalidControlPoints(String),
-}
 
+    #[error("Invalid wavetable: {0}")]
+    InvalidWavetable(String),
+
+    #[error("Invalid {field} for partial {index}: {value}. {reason}")]
+    InvalidPartial {
+        index: usize,
+        field: &'static str,
+        value: f64,
+        reason: &'static str,
+    },
+
+    #[error("No partials provided. An additive voice needs at least one partial")]
+    EmptyPartials,
+
+    #[error("Invalid FM voice: {0} operators. An FM voice needs 2 to 6 operators")]
+    InvalidOperatorCount(usize),
+
+    #[error("Invalid {field} for operator {index}: {value}. {reason}")]
+    InvalidOperator {
+        index: usize,
+        field: &'static str,
+        value: f64,
+        reason: &'static str,
+    },
+
+    #[error("Invalid {field} for plucked string: {value}. {reason}")]
+    InvalidPluck {
+        field: &'static str,
+        value: f64,
+        reason: &'static str,
+    },
+
+    #[error("Invalid sampler: {0}")]
+    InvalidSampler(String),
+
+    #[error("Invalid SoundFont: {0}")]
+    InvalidSoundFont(String),
+
+    #[error("Unknown voice '{0}'. Voices must be defined in the top-level `voices` object")]
+    UnknownVoice(String),
+
+    #[error(
+        "Conflicting waveforms. The notes already play {0}, set only one of `control_points`, `wavetable` and `partials`"
+    )]
+    ConflictingWaveform(&'static str),
+
+    #[error("{0} need the timeline format, set \"timeline\": true")]
+    TimelineOnly(&'static str), // what was set, `Wavetables`
+
+    #[error("Invalid step pattern: {0}")]
+    InvalidPattern(String),
+
+    #[error("Unknown section '{0}' in arrangement")]
+    UnknownSection(String),
+
+    #[error("Invalid arrangement: {0}")]
+    InvalidArrangement(String),
+
+    #[error("Include cycle detected: {0}")]
+    IncludeCycle(String), // the files in the cycle, `a.json -> b.json -> a.json`
+
+    #[error("Invalid include: {0}")]
+    InvalidInclude(String),
+
+    #[error("Found {} problem(s) in the input", .0.len())]
+    InvalidInput(Vec<Diagnostic>),
+}
@@ -1,16 +1,240 @@
+use crate::progress::Progress;
 use colored::*;
+use serde::Serialize;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+// Characters in the progress bar
+const PROGRESS_BAR_WIDTH: usize = 30;
+
+/// How much is printed, errors, warnings and results are always shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+// Set when stdout carries data, such as a WAV stream, so results must not mix into it
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+// Current `Verbosity` as its discriminant
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+// Set to log JSON lines to stderr instead of coloured text
+static JSON_LOG: AtomicBool = AtomicBool::new(false);
+
+/// Sets how much is printed and whether messages are logged as JSON lines on stderr
+pub fn configure(verbosity: Verbosity, json: bool) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+    JSON_LOG.store(json, Ordering::Relaxed);
+}
+
+/// Sends every following result to stderr, keeping stdout free for output data
+pub fn reserve_stdout() {
+    STDOUT_RESERVED.store(true, Ordering::Relaxed);
+}
+
+/// Runs `f` with progress messages hidden, for work whose outcome is reported another way
+pub fn quietly<T>(f: impl FnOnce() -> T) -> T {
+    let previous = VERBOSITY.swap(Verbosity::Quiet as u8, Ordering::Relaxed);
+    let result = f();
+    VERBOSITY.store(previous, Ordering::Relaxed);
+    result
+}
+
+fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Whether messages and records are printed as JSON lines
+pub fn json_log() -> bool {
+    JSON_LOG.load(Ordering::Relaxed)
+}
+
+// Prints a message to stderr at the given level if the verbosity allows it
+// Stdout is left for results, see `record`.
+fn log(level: &str, shown_from: Verbosity, msg: &str, text: String) {
+    if verbosity() < shown_from {
+        return;
+    }
+    if json_log() {
+        eprintln!("{}", serde_json::json!({ "level": level, "message": msg }));
+    } else {
+        eprintln!("{}", text);
+    }
+}
+
+// Prints a line of results to stdout, or to stderr while stdout is reserved
+fn print_line(line: String) {
+    if STDOUT_RESERVED.load(Ordering::Relaxed) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
 
 /// Prints a success message with a green checkmark
 pub fn success(msg: &str) {
-    println!("{} {}", "✓".green().bold(), msg.green());
+    log(
+        "success",
+        Verbosity::Normal,
+        msg,
+        format!("{} {}", "✓".green().bold(), msg.green()),
+    );
 }
 
 /// Prints an info message with a blue arrow
 pub fn info(msg: &str) {
-    println!("{} {}", "→".blue().bold(), msg.bright_blue());
+    log(
+        "info",
+        Verbosity::Normal,
+        msg,
+        format!("{} {}", "→".blue().bold(), msg.bright_blue()),
+    );
 }
 
 /// Prints a processing message with a spinner-like indicator
 pub fn processing(msg: &str) {
-    println!("{} {}", "⚙".yellow().bold(), msg.bright_blue());
+    log(
+        "processing",
+        Verbosity::Normal,
+        msg,
+        format!("{} {}", "⚙".yellow().bold(), msg.bright_blue()),
+    );
+}
+
+/// Prints a detail only shown with `--verbose`
+pub fn debug(msg: &str) {
+    log(
+        "debug",
+        Verbosity::Verbose,
+        msg,
+        format!("{} {}", "·".dimmed(), msg.dimmed()),
+    );
+}
+
+/// Draws a progress bar on stderr, redrawn in place until the work is finished
+/// Nothing is drawn when stderr isn't a terminal, JSON logs get a progress line instead.
+pub fn progress(progress: &Progress) {
+    if verbosity() < Verbosity::Normal {
+        return;
+    }
+    if json_log() {
+        eprintln!(
+            "{}",
+            serde_json::json!({ "level": "progress", "progress": progress })
+        );
+        return;
+    }
+    if !std::io::stderr().is_terminal() {
+        return;
+    }
+
+    let filled = (progress.fraction() * PROGRESS_BAR_WIDTH as f64).round() as usize;
+    let eta = match progress.eta {
+        Some(eta) if !progress.finished => format!(", ETA {:.1}s", eta),
+        _ => String::new(),
+    };
+    eprint!(
+        "\r{} [{}{}] {:>3.0}% {}/{} notes, {} samples{}\x1b[K",
+        "⚙".yellow().bold(),
+        "#".repeat(filled).bright_blue(),
+        ".".repeat(PROGRESS_BAR_WIDTH - filled),
+        progress.fraction() * 100.0,
+        progress.notes_done,
+        progress.notes_total,
+        progress.samples_mixed,
+        eta
+    );
+    if progress.finished {
+        eprintln!();
+    }
+}
+
+/// Prints a warning message with a yellow sign
+pub fn warning(msg: &str) {
+    log(
+        "warning",
+        Verbosity::Quiet,
+        msg,
+        format!("{} {}", "⚠".yellow().bold(), msg.yellow()),
+    );
+}
+
+/// Prints an error message with a red cross
+pub fn error(msg: &str) {
+    if json_log() {
+        eprintln!(
+            "{}",
+            serde_json::json!({ "level": "error", "message": msg })
+        );
+    } else {
+        eprintln!("{} {}", "✗".red().bold(), msg.red());
+    }
+}
+
+/// Prints a problem found by a command as part of its results, such as a lint warning
+pub fn finding(msg: &str) {
+    print_line(format!("{} {}", "⚠".yellow().bold(), msg.yellow()));
+}
+
+/// Prints the outcome of a command, as one JSON line when logging JSON
+pub fn record<T: Serialize>(value: &T, msg: &str) {
+    if json_log() {
+        match serde_json::to_string(value) {
+            Ok(line) => print_line(line),
+            Err(e) => error(&format!("Failed to serialize output: {}", e)),
+        }
+    } else {
+        print_line(format!("{} {}", "✓".green().bold(), msg.green()));
+    }
+}
+
+/// Prints labelled values as an aligned two-column table
+pub fn table(rows: &[(String, String)]) {
+    let width = rows
+        .iter()
+        .map(|(label, _)| label.chars().count())
+        .max()
+        .unwrap_or(0);
+    for (label, value) in rows {
+        let label = format!("{:<width$}", label, width = width);
+        print_line(format!("  {}  {}", label.bold(), value));
+    }
+}
+
+/// Prints rows under a header as aligned columns
+pub fn grid(header: &[&str], rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = header.iter().map(|title| title.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let pad = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    print_line(pad(header.to_vec()).bold().to_string());
+    for row in rows {
+        print_line(pad(row.iter().map(String::as_str).collect()));
+    }
+}
+
+/// Prints a value as pretty JSON for other tools to consume
+pub fn json<T: Serialize>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(text) => print_line(text),
+        Err(e) => error(&format!("Failed to serialize output: {}", e)),
+    }
 }
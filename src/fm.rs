@@ -0,0 +1,170 @@
+use crate::adsr::{ADSRConfig, ADSREnvelope};
+use crate::oscillator::Oscillator;
+use serde::Deserialize;
+use std::f64::consts::TAU;
+
+/// Fewest operators an FM voice may have, a modulator and a carrier
+pub const MIN_OPERATORS: usize = 2;
+/// Most operators an FM voice may have
+pub const MAX_OPERATORS: usize = 6;
+
+/// How the operators of an FM voice feed each other
+/// Operators are numbered from 1 in the order they are listed, and only ever modulate
+/// an operator listed before them.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+    #[default]
+    Stack, // each operator modulates the one before it, only operator 1 is heard
+    Pairs,    // 2 modulates 1, 4 modulates 3 and 6 modulates 5, odd operators are heard
+    Branch,   // every other operator modulates operator 1, which is heard
+    Parallel, // no modulation, every operator is heard
+}
+
+impl Algorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Stack => "stack",
+            Algorithm::Pairs => "pairs",
+            Algorithm::Branch => "branch",
+            Algorithm::Parallel => "parallel",
+        }
+    }
+
+    /// Position of the operator modulated by the one at `operator`, `None` for carriers
+    pub fn target(self, operator: usize) -> Option<usize> {
+        match self {
+            Algorithm::Stack => operator.checked_sub(1),
+            Algorithm::Pairs => (operator % 2 == 1).then(|| operator - 1),
+            Algorithm::Branch => (operator > 0).then_some(0),
+            Algorithm::Parallel => None,
+        }
+    }
+}
+
+/// One sine of an FM voice
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Operator {
+    pub ratio: f64, // frequency relative to the note's pitch
+    #[serde(default = "default_level")]
+    pub level: f64, // output level when the operator is heard, 0.0 to 1.0
+    #[serde(default = "default_index")]
+    pub index: f64, // modulation index, in radians of phase, when it modulates another operator
+    pub envelope: Option<ADSRConfig>, // held at full level until note-off when unset
+}
+
+fn default_level() -> f64 {
+    1.0
+}
+
+fn default_index() -> f64 {
+    1.0
+}
+
+/// Frequency modulation voice, such as electric pianos, bells and metallic tones
+#[derive(Debug, Clone, Deserialize)]
+pub struct FmVoice {
+    pub operators: Vec<Operator>,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+}
+
+impl FmVoice {
+    /// Seconds the voice keeps sounding after note-off, the longest release of a carrier
+    pub fn release(&self) -> f64 {
+        self.operators
+            .iter()
+            .enumerate()
+            .filter(|(position, _)| self.algorithm.target(*position).is_none())
+            .map(|(_, operator)| envelope_values(operator).3)
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Plays an FM voice, each operator shaped by its own envelope
+/// Operators at or above the Nyquist frequency are muted, and carrier levels adding up
+/// to more than 1.0 are scaled down together.
+pub struct FmOscillator {
+    pub frequency: f64,
+    pub amplitude: f64,
+    pub sample_rate: u32,
+    pub voice: FmVoice,
+    envelopes: Vec<ADSREnvelope>,
+    muted: Vec<bool>,
+    modulation: Vec<f64>, // phase offsets gathered for each operator while sampling
+    gain: f64,
+}
+
+impl FmOscillator {
+    // `duration` is the time in seconds before the note is released
+    pub fn new(
+        frequency: f64,
+        amplitude: f64,
+        sample_rate: u32,
+        voice: FmVoice,
+        duration: f64,
+    ) -> Self {
+        let envelopes = voice
+            .operators
+            .iter()
+            .map(|operator| {
+                let (attack, decay, sustain, release) = envelope_values(operator);
+                ADSREnvelope::new(attack, decay, sustain, release, sample_rate, duration)
+            })
+            .collect();
+        let nyquist = sample_rate as f64 / 2.0;
+        let muted = voice
+            .operators
+            .iter()
+            .map(|operator| operator.ratio * frequency >= nyquist)
+            .collect();
+        // Computed on every carrier, so muting high ones doesn't make high notes louder
+        let total: f64 = voice
+            .operators
+            .iter()
+            .enumerate()
+            .filter(|(position, _)| voice.algorithm.target(*position).is_none())
+            .map(|(_, operator)| operator.level)
+            .sum();
+        Self {
+            frequency,
+            amplitude,
+            sample_rate,
+            modulation: vec![0.0; voice.operators.len()],
+            voice,
+            envelopes,
+            muted,
+            gain: 1.0 / total.max(1.0),
+        }
+    }
+}
+
+impl Oscillator for FmOscillator {
+    fn sample(&mut self, sample_index: u32) -> f64 {
+        let time = sample_index as f64 / self.sample_rate as f64;
+        self.modulation.fill(0.0);
+        let mut sum = 0.0;
+        // Modulators come after their targets, so they are worked out first
+        for position in (0..self.voice.operators.len()).rev() {
+            let operator = self.voice.operators[position];
+            // Envelopes are stateful, so they advance even while muted
+            let level = self.envelopes[position].level(sample_index);
+            if self.muted[position] {
+                continue;
+            }
+            let x = TAU * self.frequency * operator.ratio * time + self.modulation[position];
+            match self.voice.algorithm.target(position) {
+                Some(target) => self.modulation[target] += operator.index * level * x.sin(),
+                None => sum += operator.level * level * x.sin(),
+            }
+        }
+        self.amplitude * self.gain * sum
+    }
+}
+
+// ADSR values of an operator, a plain gate when it has no envelope
+fn envelope_values(operator: &Operator) -> (f64, f64, f64, f64) {
+    operator
+        .envelope
+        .map_or((0.0, 0.0, 1.0, 0.0), |envelope| envelope.values())
+}
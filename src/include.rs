@@ -0,0 +1,215 @@
+use crate::errors::OrchestratorError;
+use serde_json::{Map, Value};
+use std::path::{Path, PathBuf};
+
+/// Key of an object that pulls in another JSON file: `{ "include": "drums/kit.json" }`
+pub const INCLUDE_KEY: &str = "include";
+// Stands for any element of an array in the location of a value
+const ITEM: &str = "[]";
+
+/// A document with its includes replaced, and the files that were read to do it
+pub struct Resolved {
+    pub value: Value,
+    pub files: Vec<PathBuf>, // canonical paths, each listed once
+}
+
+// Files being included right now, to catch cycles, and every file included so far
+#[derive(Default)]
+struct Includes {
+    chain: Vec<PathBuf>,
+    files: Vec<PathBuf>,
+}
+
+/// Replaces every `{ "include": "<path>" }` object with the contents of that file
+/// Paths are resolved against the directory of the file containing the include.
+/// Other keys next to `include` override the fields of an included object, and an
+/// included array placed inside an array is spliced into it. Relative asset paths of an
+/// included file are rewritten against its directory, so they still find their files.
+pub fn resolve_includes(value: Value, file: &Path) -> Result<Resolved, OrchestratorError> {
+    let mut includes = Includes {
+        chain: vec![canonical(file)?],
+        files: Vec::new(),
+    };
+    let value = resolve(value, &base_dir(file), &mut includes, &[])?;
+    Ok(Resolved {
+        value,
+        files: includes.files,
+    })
+}
+
+/// Same as `resolve_includes` for a document that doesn't come from a file, such as stdin
+/// Paths are resolved against the working directory.
+pub fn resolve_includes_in(value: Value, base_dir: &Path) -> Result<Resolved, OrchestratorError> {
+    let mut includes = Includes::default();
+    let value = resolve(value, base_dir, &mut includes, &[])?;
+    Ok(Resolved {
+        value,
+        files: includes.files,
+    })
+}
+
+// `location` holds the keys leading to the value from the top of the document
+fn resolve(
+    value: Value,
+    base_dir: &Path,
+    includes: &mut Includes,
+    location: &[String],
+) -> Result<Value, OrchestratorError> {
+    match value {
+        Value::Object(map) => resolve_object(map, base_dir, includes, location),
+        Value::Array(items) => {
+            let item_location = child(location, ITEM);
+            let mut resolved = Vec::with_capacity(items.len());
+            for item in items {
+                let splice = is_bare_include(&item);
+                match resolve(item, base_dir, includes, &item_location)? {
+                    Value::Array(included) if splice => resolved.extend(included),
+                    other => resolved.push(other),
+                }
+            }
+            Ok(Value::Array(resolved))
+        }
+        other => Ok(other),
+    }
+}
+
+fn resolve_object(
+    mut map: Map<String, Value>,
+    base_dir: &Path,
+    includes: &mut Includes,
+    location: &[String],
+) -> Result<Value, OrchestratorError> {
+    let include = map.remove(INCLUDE_KEY);
+
+    let mut resolved = Map::with_capacity(map.len());
+    for (key, value) in map {
+        let value = resolve(value, base_dir, includes, &child(location, &key))?;
+        resolved.insert(key, value);
+    }
+
+    let Some(include) = include else {
+        return Ok(Value::Object(resolved));
+    };
+    let relative_path = include.as_str().ok_or_else(|| {
+        OrchestratorError::InvalidInclude(format!(
+            "'{}' must be a file path string, got {}",
+            INCLUDE_KEY, include
+        ))
+    })?;
+
+    match load(&base_dir.join(relative_path), includes, location)? {
+        // Local keys override the included ones
+        Value::Object(mut included) => {
+            included.extend(resolved);
+            Ok(Value::Object(included))
+        }
+        other if resolved.is_empty() => Ok(other),
+        _ => Err(OrchestratorError::InvalidInclude(format!(
+            "{} is not an object, so it can't be combined with other keys",
+            relative_path
+        ))),
+    }
+}
+
+fn load(
+    path: &Path,
+    includes: &mut Includes,
+    location: &[String],
+) -> Result<Value, OrchestratorError> {
+    let path = canonical(path)?;
+    if includes.chain.contains(&path) {
+        let mut cycle = includes.chain.clone();
+        cycle.push(path);
+        return Err(OrchestratorError::IncludeCycle(format_chain(&cycle)));
+    }
+    if !includes.files.contains(&path) {
+        includes.files.push(path.clone());
+    }
+
+    let data = std::fs::read_to_string(&path).map_err(|e| {
+        OrchestratorError::InvalidInclude(format!("Failed to read {}: {}", path.display(), e))
+    })?;
+    let value: Value = serde_json::from_str(&data).map_err(|e| {
+        OrchestratorError::InvalidInclude(format!("Failed to parse {}: {}", path.display(), e))
+    })?;
+
+    includes.chain.push(path.clone());
+    let dir = base_dir(&path);
+    let resolved = resolve(value, &dir, includes, location).map(|mut value| {
+        rebase_assets(&mut value, &dir, location);
+        value
+    });
+    includes.chain.pop();
+    resolved
+}
+
+// Joins relative asset paths onto a directory, paths from deeper includes are absolute
+// by then and left alone
+fn rebase_assets(value: &mut Value, dir: &Path, location: &[String]) {
+    match value {
+        Value::String(path) if is_asset(location) && Path::new(path.as_str()).is_relative() => {
+            *path = dir.join(&*path).to_string_lossy().into_owned();
+        }
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                rebase_assets(value, dir, &child(location, key));
+            }
+        }
+        Value::Array(items) => {
+            // An array included in an array is spliced into it, its items sit at its place
+            let location = match location.last() {
+                Some(last) if last == ITEM => location.to_vec(),
+                _ => child(location, ITEM),
+            };
+            items
+                .iter_mut()
+                .for_each(|item| rebase_assets(item, dir, &location));
+        }
+        _ => {}
+    }
+}
+
+// Locations of paths to assets: wavetable, scale and keyboard mapping files, SoundFonts
+// and the WAV files of sampler zones
+fn is_asset(location: &[String]) -> bool {
+    let location: Vec<&str> = location.iter().map(String::as_str).collect();
+    matches!(
+        location.as_slice(),
+        ["wavetable", "file"]
+            | ["tuning", "scale" | "keyboard_map"]
+            | ["voices", _, "file"]
+            | ["voices", _, "zones", ITEM, "file"]
+    )
+}
+
+fn child(location: &[String], key: &str) -> Vec<String> {
+    let mut child = location.to_vec();
+    child.push(key.to_string());
+    child
+}
+
+// An object whose only key is the include
+fn is_bare_include(value: &Value) -> bool {
+    value
+        .as_object()
+        .is_some_and(|map| map.len() == 1 && map.contains_key(INCLUDE_KEY))
+}
+
+fn base_dir(file: &Path) -> PathBuf {
+    file.parent().map(Path::to_path_buf).unwrap_or_default()
+}
+
+fn canonical(path: &Path) -> Result<PathBuf, OrchestratorError> {
+    path.canonicalize().map_err(|e| {
+        OrchestratorError::InvalidInclude(format!("Failed to resolve {}: {}", path.display(), e))
+    })
+}
+
+// Formats an include chain as `a.json -> b.json -> a.json`
+fn format_chain(chain: &[PathBuf]) -> String {
+    chain
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
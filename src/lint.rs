@@ -0,0 +1,178 @@
+use crate::analysis::{Voice, sweep, voices};
+use crate::cli::AnyOrchestrator;
+use crate::timeline_orchestrator::CONDENSE_CONSTANT;
+use serde::Serialize;
+use std::collections::HashMap;
+
+// Summed amplitude (after condensing) above which the tanh soft clipper audibly squashes the mix
+// tanh(1.5) ≈ 0.9, so peaks at this level lose about 40% of their height
+const HARD_DRIVE_LEVEL: f64 = 1.5;
+
+/// A musical problem that doesn't stop a render but is probably not intended
+#[derive(Debug, Clone, Serialize)]
+pub struct Warning {
+    pub code: &'static str,
+    pub beat: f64,
+    pub message: String,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "beat {:.2} [{}]: {}", self.beat, self.code, self.message)
+    }
+}
+
+/// Looks for likely mistakes in a parsed score
+pub fn lint(orchestrator: &AnyOrchestrator, sample_rate: u32) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let voices = voices(orchestrator);
+
+    if let AnyOrchestrator::Timeline(timeline) = orchestrator {
+        check_drive(&voices, &mut warnings);
+        check_duplicates(&voices, &mut warnings);
+        if let Some(length) = timeline.length() {
+            check_length(&voices, length, &mut warnings);
+        }
+        check_release(&voices, &mut warnings);
+    }
+    check_aliasing(&voices, sample_rate, &mut warnings);
+
+    warnings.sort_by(|a, b| a.beat.total_cmp(&b.beat));
+    warnings
+}
+
+// Overlapping voices whose summed amplitude pushes the soft clipper hard
+fn check_drive(voices: &[Voice], warnings: &mut Vec<Warning>) {
+    // Start beat and peak level of the region currently above the threshold
+    let mut region: Option<(f64, f64)> = None;
+    for point in sweep(voices) {
+        let (beat, level) = (point.beat, point.amplitude * CONDENSE_CONSTANT);
+        match region {
+            None if level > HARD_DRIVE_LEVEL => region = Some((beat, level)),
+            Some((start, peak)) if level > HARD_DRIVE_LEVEL => {
+                region = Some((start, peak.max(level)))
+            }
+            Some((start, peak)) => {
+                warnings.push(Warning {
+                    code: "hard-clipping",
+                    beat: start,
+                    message: format!(
+                        "Overlapping notes up to beat {:.2} sum to an amplitude of {:.2}, the tanh soft clipper will squash them (lower their amplitudes below {:.2} combined)",
+                        beat, peak, HARD_DRIVE_LEVEL
+                    ),
+                });
+                region = None;
+            }
+            None => {}
+        }
+    }
+}
+
+// Identical notes stacked on top of each other
+fn check_duplicates(voices: &[Voice], warnings: &mut Vec<Warning>) {
+    let mut seen: HashMap<(String, u64, u64), usize> = HashMap::new();
+    for voice in voices {
+        let key = (
+            voice.label.clone(),
+            voice.start.to_bits(),
+            voice.end.to_bits(),
+        );
+        let count = seen.entry(key).or_insert(0);
+        *count += 1;
+        if *count == 2 {
+            warnings.push(Warning {
+                code: "duplicate-note",
+                beat: voice.start,
+                message: format!(
+                    "{} is played more than once with the same start and length",
+                    voice.label
+                ),
+            });
+        }
+    }
+}
+
+// Voices that don't fit in the declared song length
+fn check_length(voices: &[Voice], length: f64, warnings: &mut Vec<Warning>) {
+    for voice in voices.iter().filter(|voice| voice.end > length) {
+        let message = if voice.start >= length {
+            format!(
+                "{} starts after the declared length of {} beats and will not be heard",
+                voice.label, length
+            )
+        } else {
+            format!(
+                "{} ends at beat {:.2}, past the declared length of {} beats, and will be cut off",
+                voice.label, voice.end, length
+            )
+        };
+        warnings.push(Warning {
+            code: "past-length",
+            beat: voice.start,
+            message,
+        });
+    }
+}
+
+// Release tails that run into the next note played by the same voice or waveform
+fn check_release(voices: &[Voice], warnings: &mut Vec<Warning>) {
+    let mut by_instrument: HashMap<Option<&str>, Vec<&Voice>> = HashMap::new();
+    for voice in voices.iter().filter(|voice| voice.frequency.is_some()) {
+        by_instrument
+            .entry(voice.instrument.as_deref())
+            .or_default()
+            .push(voice);
+    }
+
+    for notes in by_instrument.values_mut() {
+        notes.sort_by(|a, b| a.start.total_cmp(&b.start));
+        for note in notes.iter().filter(|note| note.release > 0.0) {
+            // Notes starting before this one ends play alongside it, not after it
+            let Some(next) = notes
+                .iter()
+                .find(|next| next.start >= note.end && !std::ptr::eq(**next, *note))
+            else {
+                continue;
+            };
+            let gap = next.start - note.end;
+            if gap < note.release {
+                warnings.push(Warning {
+                    code: "release-overlap",
+                    beat: note.end,
+                    message: format!(
+                        "The release of {} ({:.2} beats) is longer than the {:.2} beat gap before {} plays at beat {:.2}",
+                        note.label, note.release, gap, next.label, next.start
+                    ),
+                });
+            }
+        }
+    }
+}
+
+// Pitches so high that their harmonics fold back below Nyquist
+// The Bézier waveform is band-limited instead, which leaves such notes no harmonics.
+fn check_aliasing(voices: &[Voice], sample_rate: u32, warnings: &mut Vec<Warning>) {
+    let nyquist = sample_rate as f64 / 2.0;
+    for voice in voices {
+        if let Some(frequency) = voice.frequency
+            && frequency > nyquist / 2.0
+        {
+            let effect = if voice.bezier {
+                "it has no room for harmonics and plays as a sine"
+            } else {
+                "its harmonics will alias"
+            };
+            warnings.push(Warning {
+                code: "aliasing",
+                beat: voice.start,
+                message: format!(
+                    "{} at {:.0} Hz is above half the Nyquist frequency ({:.0} Hz), {}",
+                    voice.label,
+                    frequency,
+                    nyquist / 2.0,
+                    effect
+                ),
+            });
+        }
+    }
+}
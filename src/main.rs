@@ -1,61 +1,283 @@
-use crate::cli::{AnyOrchestrator, get_filename, get_music_input, parse_args};
+use crate::batch::RenderStats;
+use crate::cli::{
+    AnyOrchestrator, Command, ErrorFormat, InfoFormat, LogFormat, get_filename, get_music_input,
+    is_stdio, parse_args,
+};
+use crate::errors::OrchestratorError;
 use anyhow::{Context, Result};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 mod adsr;
+mod analysis;
+mod arrangement;
+mod batch;
+mod bezier;
 mod cli;
+mod diagnostics;
 mod errors;
 mod feedback;
+mod fm;
+mod include;
+mod lint;
+mod noise;
 mod orchestrator;
 mod oscillator;
+mod pattern;
+mod percussion;
+mod pluck;
+mod progress;
+mod sampler;
+mod scala;
+mod soundfont;
+mod summary;
 mod timeline_orchestrator;
+mod tuning;
 mod validation;
+mod voice;
+mod watch;
 mod wav;
+mod wavetable;
 
 const SAMPLE_RATE: u32 = 44100;
 
 fn main() -> Result<()> {
     // Parse command-line arguments
     let args = parse_args();
+    feedback::configure(args.verbosity(), matches!(args.log_format, LogFormat::Json));
+
+    let result = match args.subcommand() {
+        Command::Render(render_args) if render_args.watch => {
+            watch(&render_args.input_files, render_args.output.as_deref())
+        }
+        Command::Render(render_args) if batch::is_single(&render_args.input_files) => render(
+            &render_args.input_files[0],
+            render_args.output.as_deref(),
+            args.error_format,
+        ),
+        Command::Render(render_args) => {
+            render_batch(&render_args.input_files, render_args.output.as_deref())
+        }
+        Command::Validate(input) => check(&input.input_file, args.error_format, false),
+        Command::Lint(input) => check(&input.input_file, args.error_format, true),
+        Command::Info(info_args) => {
+            info(&info_args.input_file, args.error_format, info_args.format)
+        }
+    };
+
+    // Tools reading JSON logs get the final error as a log line too
+    if let Err(error) = &result
+        && feedback::json_log()
+    {
+        feedback::error(&format!("{:#}", error));
+        std::process::exit(1);
+    }
+    result
+}
+
+// Loads and validates the input, printing every problem found before failing
+fn load(input_file: &Path, error_format: ErrorFormat) -> Result<AnyOrchestrator> {
+    feedback::info(&format!("Loading input file: {}", input_file.display()));
+    match get_music_input(input_file, SAMPLE_RATE) {
+        Ok(orchestrator) => Ok(orchestrator),
+        Err(error) => {
+            // Report every problem in the input before giving up
+            if let Some(OrchestratorError::InvalidInput(problems)) = error.downcast_ref() {
+                match error_format {
+                    ErrorFormat::Text => problems
+                        .iter()
+                        .for_each(|problem| feedback::error(&problem.to_string())),
+                    ErrorFormat::Json => feedback::json(&serde_json::json!({ "errors": problems })),
+                }
+            }
+            Err(error.context("Failed to load and parse music input"))
+        }
+    }
+}
+
+fn render(input_file: &Path, output: Option<&Path>, error_format: ErrorFormat) -> Result<()> {
+    // Keep stdout clean for the WAV stream
+    if output.is_some_and(is_stdio) {
+        feedback::reserve_stdout();
+    }
 
     // Step 1: Load input file
-    feedback::info(&format!(
-        "Loading input file: {}",
-        args.input_file.display()
-    ));
-    let orchestrator: AnyOrchestrator =
-        get_music_input(&args.input_file).context("Failed to load and parse music input")?;
+    let orchestrator = load(input_file, error_format)?;
 
     // Step 2: Validate and show configuration
     feedback::success("Input validated successfully");
-    let oscillator_type = if orchestrator.is_bezier() {
-        "Bezier curves"
-    } else {
-        "sine waves"
-    };
     feedback::info(&format!(
         "Generating sounds using {} ({} notes)",
-        oscillator_type,
+        orchestrator.waveform().name(),
         orchestrator.note_count()
     ));
 
     // Step 3: Generate PCM samples
     feedback::processing("Processing notes and generating samples...");
+    feedback::debug(&format!("Sample rate: {} Hz", SAMPLE_RATE));
+    let started = Instant::now();
     let pcm_samples: Vec<i16> = orchestrator
-        .pcm_samples(SAMPLE_RATE)
+        .pcm_samples_with_progress(SAMPLE_RATE, &mut feedback::progress)
         .context("Failed to generate PCM samples")?;
     feedback::success(&format!("Generated {} samples", pcm_samples.len()));
+    feedback::debug(&format!(
+        "Generation took {:.1} ms",
+        started.elapsed().as_secs_f64() * 1000.0
+    ));
+    for (label, range) in orchestrator.section_ranges(SAMPLE_RATE) {
+        feedback::info(&format!(
+            "Section {}: samples {}..{} ({:.2}s - {:.2}s)",
+            label,
+            range.start,
+            range.end,
+            range.start as f64 / SAMPLE_RATE as f64,
+            range.end as f64 / SAMPLE_RATE as f64
+        ));
+    }
 
     // Step 4: Prepare output file
-    let filename: String =
-        get_filename(&args.input_file).context("Failed to extract filename from input path")?;
-    let output_path = format!("output/{}.wav", filename);
+    let output_path = output_path(input_file, output)?;
 
     // Step 5: Write WAV file
-    feedback::processing(&format!("Writing WAV file to {}...", output_path));
-    wav::write(&output_path, &pcm_samples, SAMPLE_RATE).context("Failed to write WAV file")?;
+    let written = if is_stdio(&output_path) {
+        feedback::processing("Writing WAV data to stdout...");
+        let mut stdout = BufWriter::new(std::io::stdout().lock());
+        wav::write_to(&mut stdout, &pcm_samples, SAMPLE_RATE)
+            .context("Failed to write WAV data to stdout")?;
+        "Successfully wrote WAV data to stdout".to_string()
+    } else {
+        let output_path = output_path.display().to_string();
+        feedback::processing(&format!("Writing WAV file to {}...", output_path));
+        wav::write(&output_path, &pcm_samples, SAMPLE_RATE).context("Failed to write WAV file")?;
+        format!("Successfully created: {}", output_path)
+    };
 
     // Success!
-    feedback::success(&format!("Successfully created: {}", output_path));
+    let stats = RenderStats::new(
+        &output_path,
+        &pcm_samples,
+        orchestrator.note_count(),
+        SAMPLE_RATE,
+    );
+    feedback::record(
+        &stats,
+        &format!(
+            "{} ({} samples, {:.2}s, peak {:.1} dBFS)",
+            written,
+            stats.samples,
+            stats.duration,
+            stats.peak_dbfs()
+        ),
+    );
+
+    Ok(())
+}
+
+// The given output path, or output/<name>.wav named after the input
+fn output_path(input_file: &Path, output: Option<&Path>) -> Result<PathBuf> {
+    match output {
+        Some(path) => Ok(path.to_path_buf()),
+        None => {
+            let filename: String =
+                get_filename(input_file).context("Failed to extract filename from input path")?;
+            Ok(PathBuf::from(format!("output/{}.wav", filename)))
+        }
+    }
+}
+
+// Re-renders a single file every time it changes
+fn watch(inputs: &[PathBuf], output: Option<&Path>) -> Result<()> {
+    let [input_file] = inputs else {
+        anyhow::bail!("--watch follows a single input file");
+    };
+    if is_stdio(input_file) || output.is_some_and(is_stdio) {
+        anyhow::bail!("--watch needs an input file and an output file, not stdin or stdout");
+    }
+    let output_path = output_path(input_file, output)?;
+    watch::watch(input_file, &output_path, SAMPLE_RATE)
+}
+
+// Renders many files at once and reports how each of them went
+fn render_batch(inputs: &[PathBuf], output_dir: Option<&Path>) -> Result<()> {
+    if output_dir.is_some_and(is_stdio) {
+        anyhow::bail!("Several files can't be written to stdout, give an output directory instead");
+    }
+    let output_dir = output_dir.unwrap_or(Path::new("output"));
+    let files = batch::expand_inputs(inputs).context("Failed to collect input files")?;
+    feedback::processing(&format!(
+        "Rendering {} files into {}...",
+        files.len(),
+        output_dir.display()
+    ));
+
+    // Per-file messages would interleave, the summary reports each file instead
+    let results = feedback::quietly(|| batch::render_all(&files, output_dir, SAMPLE_RATE));
+    batch::print_summary(&results);
+
+    let failed = results
+        .iter()
+        .filter(|result| result.outcome.is_err())
+        .count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} renders failed", failed, results.len());
+    }
+    feedback::success(&format!("Rendered {} files", results.len()));
+    Ok(())
+}
+
+// Validates the input without rendering it, optionally looking for musical problems too
+fn check(input_file: &Path, error_format: ErrorFormat, with_lint: bool) -> Result<()> {
+    let orchestrator = load(input_file, error_format)?;
+    let warnings = if with_lint {
+        lint::lint(&orchestrator, SAMPLE_RATE)
+    } else {
+        Vec::new()
+    };
+
+    match error_format {
+        ErrorFormat::Text => {
+            // JSON records carry the warnings themselves
+            if !feedback::json_log() {
+                for warning in &warnings {
+                    feedback::finding(&warning.to_string());
+                }
+            }
+            feedback::record(
+                &serde_json::json!({
+                    "input": input_file,
+                    "notes": orchestrator.note_count(),
+                    "warnings": warnings,
+                }),
+                &format!(
+                    "{} is valid ({} notes, {} warnings)",
+                    input_file.display(),
+                    orchestrator.note_count(),
+                    warnings.len()
+                ),
+            );
+        }
+        ErrorFormat::Json => {
+            feedback::json(&serde_json::json!({ "errors": [], "warnings": warnings }))
+        }
+    }
+
+    // Warnings are advisory, the score can still be rendered
+    Ok(())
+}
+
+// Prints an overview of the score without rendering it
+fn info(input_file: &Path, error_format: ErrorFormat, format: InfoFormat) -> Result<()> {
+    let orchestrator = load(input_file, error_format)?;
+    let summary = summary::summarize(&orchestrator);
+
+    match format {
+        InfoFormat::Table => {
+            feedback::success(&format!("Summary of {}", input_file.display()));
+            feedback::table(&summary.rows());
+        }
+        InfoFormat::Json => feedback::json(&summary),
+    }
 
     Ok(())
 }
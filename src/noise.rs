@@ -0,0 +1,98 @@
+use serde::Deserialize;
+
+/// Seed used when a track does not provide one, so renders stay reproducible
+pub const DEFAULT_SEED: u64 = 0x5EED;
+
+/// Small xorshift64* random number generator
+/// Seeded explicitly so the same input file always renders the same noise
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Run the seed through splitmix64 so nearby seeds give unrelated streams
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        // xorshift gets stuck on a zero state
+        Self {
+            state: if z == 0 { 0x9E37_79B9_7F4A_7C15 } else { z },
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // Returns a uniformly distributed value in [-1.0, 1.0)
+    pub fn next_bipolar(&mut self) -> f64 {
+        let unit = (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64;
+        unit * 2.0 - 1.0
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum NoiseColor {
+    White,
+    Pink,
+    Brown,
+}
+
+pub struct NoiseGenerator {
+    color: NoiseColor,
+    rng: Rng,
+    pink_state: [f64; 7], // filter bank for pink noise
+    brown_state: f64,     // running integral for brown noise
+}
+
+impl NoiseGenerator {
+    pub fn new(color: NoiseColor, seed: u64) -> Self {
+        Self {
+            color,
+            rng: Rng::new(seed),
+            pink_state: [0.0; 7],
+            brown_state: 0.0,
+        }
+    }
+
+    // Noise is stateful, so samples are pulled one after another instead of by index
+    pub fn sample(&mut self) -> f64 {
+        let white = self.rng.next_bipolar();
+        match self.color {
+            NoiseColor::White => white,
+            NoiseColor::Pink => self.pink(white),
+            NoiseColor::Brown => self.brown(white),
+        }
+    }
+
+    // Paul Kellet's refined filter: a sum of one-pole filters approximating a -3dB/octave slope
+    fn pink(&mut self, white: f64) -> f64 {
+        let b = &mut self.pink_state;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.1538520;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.0168980;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        // Scale back to roughly [-1.0, 1.0]
+        pink * 0.11
+    }
+
+    // Leaky integration of white noise gives a -6dB/octave slope
+    fn brown(&mut self, white: f64) -> f64 {
+        self.brown_state = (self.brown_state + 0.02 * white) / 1.02;
+        // Scale back to roughly [-1.0, 1.0]
+        (self.brown_state * 3.5).clamp(-1.0, 1.0)
+    }
+}
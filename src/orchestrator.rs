@@ -1,38 +1,51 @@
+use crate::bezier::{BezierWave, ControlPoints, Shaping};
 use crate::errors::OrchestratorError;
-use crate::oscillator::{BezierOscillator, SinOscillator};
-use crate::validation::{validate_bpm, validate_control_points, validate_notes};
+use crate::oscillator::{BezierOscillator, Oscillator, SinOscillator, Waveform};
+use crate::progress::{Progress, ProgressTracker};
+use crate::tuning::{Pitch, Tuning};
+use crate::validation::{validate_bpm, validate_control_points, validate_notes, validate_pitch};
 use serde::Deserialize;
 
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// Scientific pitch name of a note, e.g. `C#4`
+pub fn note_name(id: u8, octave: u8) -> String {
+    match NOTE_NAMES.get(id as usize) {
+        Some(name) => format!("{}{}", name, octave),
+        None => format!("#{}/{}", id, octave),
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Note {
-    id: u8,
-    octave: u8,
+    #[serde(flatten)]
+    pitch: Pitch,
     beats: f64,
     amplitude: f64,
 }
 
 impl Note {
     // Public getters for validation
-    pub fn id(&self) -> u8 {
-        self.id
-    }
-
-    pub fn octave(&self) -> u8 {
-        self.octave
+    pub fn pitch(&self) -> &Pitch {
+        &self.pitch
     }
 
     pub fn amplitude(&self) -> f64 {
         self.amplitude
     }
 
-    pub fn frequency(&self) -> Result<f64, OrchestratorError> {
-        if self.id > 11 {
-            return Err(OrchestratorError::InvalidNoteId(self.id));
-        }
-        let multiplier =
-            (2_f64).powf(((self.id as f64 - 9.0) + 12.0 * (self.octave as f64 - 4.0)) / 12.0);
-        let frequency = 440.0 * multiplier;
-        Ok(frequency)
+    pub fn beats(&self) -> f64 {
+        self.beats
+    }
+
+    pub fn name(&self, tuning: &Tuning) -> String {
+        self.pitch.name(tuning)
+    }
+
+    pub fn frequency(&self, tuning: &Tuning) -> Result<f64, OrchestratorError> {
+        tuning.frequency(&self.pitch)
     }
 }
 
@@ -43,16 +56,25 @@ pub enum Orchestrator {
 
 impl Orchestrator {
     pub fn pcm_samples(&self, sample_rate: u32) -> Result<Vec<i16>, OrchestratorError> {
+        self.pcm_samples_with_progress(sample_rate, &mut |_| {})
+    }
+
+    // Same as `pcm_samples`, calling `on_progress` as notes are generated
+    pub fn pcm_samples_with_progress(
+        &self,
+        sample_rate: u32,
+        on_progress: &mut dyn FnMut(&Progress),
+    ) -> Result<Vec<i16>, OrchestratorError> {
         match self {
-            Orchestrator::Sine(sine) => sine.pcm_samples(sample_rate),
-            Orchestrator::Bezier(bezier) => bezier.pcm_samples(sample_rate),
+            Orchestrator::Sine(sine) => sine.pcm_samples(sample_rate, on_progress),
+            Orchestrator::Bezier(bezier) => bezier.pcm_samples(sample_rate, on_progress),
         }
     }
 
     pub fn new(
         bpm: u8,
         notes: Vec<Note>,
-        control_points: Option<Vec<f64>>,
+        control_points: Option<ControlPoints>,
     ) -> Result<Self, OrchestratorError> {
         // Validate inputs
         validate_bpm(bpm)?;
@@ -63,15 +85,68 @@ impl Orchestrator {
             Ok(Orchestrator::Bezier(BezierOrchestrator {
                 bpm,
                 notes,
-                control_points: points.clone(),
+                wave: BezierWave::new(points.clone(), Shaping::default()),
+                tuning: Tuning::default(),
             }))
         } else {
-            Ok(Orchestrator::Sine(SineOrchestrator { bpm, notes }))
+            Ok(Orchestrator::Sine(SineOrchestrator {
+                bpm,
+                notes,
+                tuning: Tuning::default(),
+            }))
+        }
+    }
+
+    // Sets how note ids and octaves map to frequencies, every note must have a pitch in it
+    pub fn with_tuning(mut self, tuning: Tuning) -> Result<Self, OrchestratorError> {
+        let notes = match &self {
+            Orchestrator::Sine(sine) => &sine.notes,
+            Orchestrator::Bezier(bezier) => &bezier.notes,
+        };
+        for note in notes {
+            validate_pitch(&note.pitch, &tuning)?;
+        }
+        match &mut self {
+            Orchestrator::Sine(sine) => sine.tuning = tuning,
+            Orchestrator::Bezier(bezier) => bezier.tuning = tuning,
+        }
+        Ok(self)
+    }
+
+    pub fn tuning(&self) -> &Tuning {
+        match self {
+            Orchestrator::Sine(sine) => &sine.tuning,
+            Orchestrator::Bezier(bezier) => &bezier.tuning,
         }
     }
 
-    pub fn is_bezier(&self) -> bool {
-        matches!(self, Orchestrator::Bezier(_))
+    // Closes the loop and removes the DC of the Bezier waveform, sine waves need neither
+    pub fn with_shaping(mut self, shaping: Shaping) -> Self {
+        if let Orchestrator::Bezier(bezier) = &mut self {
+            bezier.wave = BezierWave::new(bezier.wave.control_points().clone(), shaping);
+        }
+        self
+    }
+
+    pub fn waveform(&self) -> Waveform {
+        match self {
+            Orchestrator::Sine(_) => Waveform::Sine,
+            Orchestrator::Bezier(bezier) => Waveform::Bezier(bezier.wave.clone()),
+        }
+    }
+
+    pub fn bpm(&self) -> u8 {
+        match self {
+            Orchestrator::Sine(sine) => sine.bpm,
+            Orchestrator::Bezier(bezier) => bezier.bpm,
+        }
+    }
+
+    pub fn notes(&self) -> &[Note] {
+        match self {
+            Orchestrator::Sine(sine) => &sine.notes,
+            Orchestrator::Bezier(bezier) => &bezier.notes,
+        }
     }
 
     pub fn note_count(&self) -> usize {
@@ -82,28 +157,48 @@ impl Orchestrator {
     }
 }
 
+// Samples a sequence of notes adds up to, for progress reporting
+fn samples_to_generate(notes: &[Note], seconds_per_beat: f64, sample_rate: u32) -> usize {
+    notes
+        .iter()
+        .map(|note| (note.beats * seconds_per_beat * sample_rate as f64) as u32 as usize)
+        .sum()
+}
+
 pub struct SineOrchestrator {
     bpm: u8, //beats per min
     notes: Vec<Note>,
+    tuning: Tuning,
 }
 
 impl SineOrchestrator {
-    pub fn pcm_samples(&self, sample_rate: u32) -> Result<Vec<i16>, OrchestratorError> {
+    pub fn pcm_samples(
+        &self,
+        sample_rate: u32,
+        on_progress: &mut dyn FnMut(&Progress),
+    ) -> Result<Vec<i16>, OrchestratorError> {
         let mut samples: Vec<i16> = Vec::new();
         let seconds_per_beat = 60.0 / self.bpm as f64;
+        let mut progress = ProgressTracker::new(
+            self.notes.len(),
+            samples_to_generate(&self.notes, seconds_per_beat, sample_rate),
+            on_progress,
+        );
 
         for note in &self.notes {
-            let wave = SinOscillator {
+            let mut wave = SinOscillator {
                 amplitude: note.amplitude,
-                frequency: note.frequency()?,
-                sample_rate: sample_rate,
+                frequency: note.frequency(&self.tuning)?,
+                sample_rate,
             };
             let duration = note.beats * seconds_per_beat;
             let samples_per_note = (duration * sample_rate as f64) as u32;
             for i in 0..samples_per_note {
                 samples.push(wave.pcm_sample(i));
             }
+            progress.advance(1, samples_per_note as usize);
         }
+        progress.finish();
         Ok(samples)
     }
 }
@@ -111,27 +206,39 @@ impl SineOrchestrator {
 pub struct BezierOrchestrator {
     bpm: u8, //beats per min
     notes: Vec<Note>,
-    control_points: Vec<f64>,
+    wave: BezierWave,
+    tuning: Tuning,
 }
 
 impl BezierOrchestrator {
-    pub fn pcm_samples(&self, sample_rate: u32) -> Result<Vec<i16>, OrchestratorError> {
+    pub fn pcm_samples(
+        &self,
+        sample_rate: u32,
+        on_progress: &mut dyn FnMut(&Progress),
+    ) -> Result<Vec<i16>, OrchestratorError> {
         let mut samples: Vec<i16> = Vec::new();
         let seconds_per_beat = 60.0 / self.bpm as f64;
+        let mut progress = ProgressTracker::new(
+            self.notes.len(),
+            samples_to_generate(&self.notes, seconds_per_beat, sample_rate),
+            on_progress,
+        );
 
         for note in &self.notes {
-            let wave = BezierOscillator::new(
-                note.frequency()?,
+            let mut wave = BezierOscillator::new(
+                note.frequency(&self.tuning)?,
                 note.amplitude,
                 sample_rate,
-                self.control_points.clone(),
+                self.wave.clone(),
             )?;
             let duration = note.beats * seconds_per_beat;
             let samples_per_note = (duration * sample_rate as f64) as u32;
             for i in 0..samples_per_note {
                 samples.push(wave.pcm_sample(i));
             }
+            progress.advance(1, samples_per_note as usize);
         }
+        progress.finish();
         Ok(samples)
     }
 }
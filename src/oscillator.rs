@@ -1,8 +1,83 @@
+use crate::adsr::ADSREnvelope;
+use crate::bezier::BezierWave;
 use crate::errors::OrchestratorError;
 use crate::validation::validate_control_points;
-use std::f64::consts::PI;
+use crate::wavetable::WavetableWave;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{PI, TAU};
 
 const PCM_BIT_RANGE: u32 = 2_u32.pow(16 - 1) - 1;
+// Points measured over one cycle of a waveform
+const CYCLE_POINTS: usize = 4096;
+// Means closer to 0.0 than this are rounding errors
+const DC_ROUNDING: f64 = 1e-12;
+
+/// Level of one cycle of a waveform at full amplitude
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CycleStats {
+    pub dc: f64, // mean value, 0.0 when the wave is centred
+    pub rms: f64,
+    pub peak: f64, // largest absolute value
+}
+
+/// Measures one cycle of a waveform given as a function of the phase, from 0.0 to 1.0
+pub fn measure_cycle(wave: impl Fn(f64) -> f64) -> CycleStats {
+    let (mut sum, mut squares, mut peak) = (0.0, 0.0, 0.0_f64);
+    for point in 0..CYCLE_POINTS {
+        let value = wave((point as f64 + 0.5) / CYCLE_POINTS as f64);
+        sum += value;
+        squares += value * value;
+        peak = peak.max(value.abs());
+    }
+    // Rounding errors would print a centred wave as -0.000
+    let dc = sum / CYCLE_POINTS as f64;
+    CycleStats {
+        dc: if dc.abs() < DC_ROUNDING { 0.0 } else { dc },
+        rms: (squares / CYCLE_POINTS as f64).sqrt(),
+        peak,
+    }
+}
+
+/// Waveform the pitched notes of a score play
+#[derive(Debug, Clone)]
+pub enum Waveform {
+    Sine,
+    Bezier(BezierWave),
+    Wavetable(WavetableWave),
+    Additive(Vec<Partial>),
+}
+
+impl Waveform {
+    // What each waveform plays, as used in messages
+    pub const SINE_NAME: &'static str = "sine waves";
+    pub const BEZIER_NAME: &'static str = "Bezier curves";
+    pub const WAVETABLE_NAME: &'static str = "a wavetable";
+    pub const ADDITIVE_NAME: &'static str = "additive partials";
+
+    /// What the notes play, as used in messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            Waveform::Sine => Self::SINE_NAME,
+            Waveform::Bezier(_) => Self::BEZIER_NAME,
+            Waveform::Wavetable(_) => Self::WAVETABLE_NAME,
+            Waveform::Additive(_) => Self::ADDITIVE_NAME,
+        }
+    }
+}
+
+/// Common interface for waveforms that can be sampled by index
+/// Indexes are asked for in order, so oscillators may keep state between samples.
+pub trait Oscillator {
+    // Generate the wave's value at the given sample index
+    fn sample(&mut self, sample_index: u32) -> f64;
+
+    // Returns the sample converted to a 16bit PCM int
+    fn pcm_sample(&mut self, sample_index: u32) -> i16 {
+        // Clamp the value to handle clipping
+        let float_sample = self.sample(sample_index).clamp(-1.0, 1.0);
+        (float_sample * (PCM_BIT_RANGE as f64)) as i16
+    }
+}
 
 pub struct SinOscillator {
     pub frequency: f64,
@@ -10,27 +85,76 @@ pub struct SinOscillator {
     pub sample_rate: u32,
 }
 
-impl SinOscillator {
+impl Oscillator for SinOscillator {
     // Generate sample's sin value at given time
-    pub fn sample(&self, sample_index: u32) -> f64 {
+    fn sample(&mut self, sample_index: u32) -> f64 {
         let x = (2.0 * PI * self.frequency * sample_index as f64) / self.sample_rate as f64;
         self.amplitude * x.sin()
     }
+}
 
-    // Returns the sample converted to a 16bit PCM int
-    pub fn pcm_sample(&self, sample_index: u32) -> i16 {
-        // Clamp the value to handle clipping
-        let float_sample = self.sample(sample_index).clamp(-1.0, 1.0);
-        let pcm_value = (float_sample * (PCM_BIT_RANGE as f64)) as i16;
-        pcm_value
+/// One sine of an additive voice, set in the top-level `partials` list
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Partial {
+    pub ratio: f64, // frequency relative to the note's pitch
+    pub amplitude: f64,
+    pub decay: Option<f64>, // seconds to fall to about 37%, held for the whole note when unset
+}
+
+/// Sum of sines at ratios of the note's pitch, such as organ drawbars or bell modes
+/// Partials at or above the Nyquist frequency are dropped, and amplitudes adding up to
+/// more than 1.0 are scaled down together.
+pub struct AdditiveOscillator {
+    pub frequency: f64,
+    pub amplitude: f64,
+    pub sample_rate: u32,
+    pub partials: Vec<Partial>, // only the ones below Nyquist
+    gain: f64,
+}
+
+impl AdditiveOscillator {
+    pub fn new(frequency: f64, amplitude: f64, sample_rate: u32, partials: &[Partial]) -> Self {
+        // Computed on every partial, so dropping high ones doesn't make high notes louder
+        let total: f64 = partials.iter().map(|partial| partial.amplitude).sum();
+        let nyquist = sample_rate as f64 / 2.0;
+        Self {
+            frequency,
+            amplitude,
+            sample_rate,
+            partials: partials
+                .iter()
+                .filter(|partial| partial.ratio * frequency < nyquist)
+                .copied()
+                .collect(),
+            gain: 1.0 / total.max(1.0),
+        }
+    }
+}
+
+impl Oscillator for AdditiveOscillator {
+    fn sample(&mut self, sample_index: u32) -> f64 {
+        let time = sample_index as f64 / self.sample_rate as f64;
+        let sum: f64 = self
+            .partials
+            .iter()
+            .map(|partial| {
+                let x = 2.0 * PI * self.frequency * partial.ratio * time;
+                let level = partial.decay.map_or(1.0, |decay| (-time / decay).exp());
+                partial.amplitude * level * x.sin()
+            })
+            .sum();
+        self.amplitude * self.gain * sum
     }
 }
 
+/// Plays a Bézier waveform from the band-limited table that suits its frequency,
+/// so its harmonics don't fold back below the Nyquist frequency
 pub struct BezierOscillator {
     pub frequency: f64,
     pub amplitude: f64,
     pub sample_rate: u32,
-    pub control_points: Vec<f64>,
+    pub wave: BezierWave,
+    level: usize, // table level played
 }
 
 impl BezierOscillator {
@@ -38,41 +162,84 @@ impl BezierOscillator {
         frequency: f64,
         amplitude: f64,
         sample_rate: u32,
-        control_points: Vec<f64>,
+        wave: BezierWave,
     ) -> Result<Self, OrchestratorError> {
         // Validate control points using centralized validation
-        validate_control_points(&control_points)?;
+        validate_control_points(wave.control_points())?;
 
+        let level = wave.table().level(frequency, sample_rate);
         Ok(Self {
             frequency,
             amplitude,
             sample_rate,
-            control_points,
+            wave,
+            level,
         })
     }
+}
 
-    pub fn sample(&self, sample_index: u32) -> f64 {
+impl Oscillator for BezierOscillator {
+    fn sample(&mut self, sample_index: u32) -> f64 {
         // phase of the wave at the given sample index
         let phase: f64 = ((sample_index as f64 * self.frequency) / self.sample_rate as f64).fract();
-        let bezier_value = self.calculate_bezier_value(phase);
-        bezier_value * self.amplitude as f64
+        let bezier_value = self.wave.table().value(self.level, phase);
+        bezier_value * self.amplitude
+    }
+}
+
+/// Plays the frames of a wavetable, crossfading between them as the position moves
+pub struct WavetableOscillator {
+    pub frequency: f64,
+    pub amplitude: f64,
+    pub sample_rate: u32,
+    pub wave: WavetableWave,
+    level: usize,                   // table level played
+    envelope: Option<ADSREnvelope>, // moves the position when the wavetable has one
+}
+
+impl WavetableOscillator {
+    // `duration` is the time in seconds before the note is released
+    pub fn new(
+        frequency: f64,
+        amplitude: f64,
+        sample_rate: u32,
+        wave: WavetableWave,
+        duration: f64,
+    ) -> Self {
+        let level = wave.frames().level(frequency, sample_rate);
+        let envelope = wave.morph().envelope.map(|envelope| {
+            let (attack, decay, sustain, release) = envelope.adsr.values();
+            ADSREnvelope::new(attack, decay, sustain, release, sample_rate, duration)
+        });
+        Self {
+            frequency,
+            amplitude,
+            sample_rate,
+            wave,
+            level,
+            envelope,
+        }
     }
 
-    fn calculate_bezier_value(&self, t: f64) -> f64 {
-        let p0 = self.control_points[0];
-        let p1 = self.control_points[1];
-        let p2 = self.control_points[2];
-        let p3 = self.control_points[3];
-        let one_minus_t = 1.0 - t;
-        one_minus_t.powf(3.0) * p0
-            + 3.0 * one_minus_t.powf(2.0) * t * p1
-            + 3.0 * one_minus_t * t.powf(2.0) * p2
-            + t.powf(3.0) * p3
+    // Position across the frames at a sample index, between 0.0 and 1.0
+    fn position(&mut self, sample_index: u32) -> f64 {
+        let morph = *self.wave.morph();
+        let mut position = morph.position;
+        if let (Some(envelope), Some(shape)) = (&mut self.envelope, morph.envelope) {
+            position += shape.amount * envelope.level(sample_index);
+        }
+        if let Some(lfo) = morph.lfo {
+            let time = sample_index as f64 / self.sample_rate as f64;
+            position += lfo.depth * (TAU * lfo.rate * time).sin();
+        }
+        position.clamp(0.0, 1.0)
     }
+}
 
-    pub fn pcm_sample(&self, sample_index: u32) -> i16 {
-        let float_sample = self.sample(sample_index).clamp(-1.0, 1.0);
-        let pcm_value = (float_sample * (PCM_BIT_RANGE as f64)) as i16;
-        pcm_value
+impl Oscillator for WavetableOscillator {
+    fn sample(&mut self, sample_index: u32) -> f64 {
+        let phase: f64 = ((sample_index as f64 * self.frequency) / self.sample_rate as f64).fract();
+        let position = self.position(sample_index);
+        self.wave.frames().value(self.level, position, phase) * self.amplitude
     }
 }
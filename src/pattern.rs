@@ -0,0 +1,216 @@
+use crate::errors::OrchestratorError;
+use crate::percussion::{DrumHit, PercussionNote};
+use crate::timeline_orchestrator::TimelineNote;
+use crate::tuning::Pitch;
+use crate::validation::{FieldProblem, validate_amplitude, validate_cents};
+use serde::Deserialize;
+
+// Step characters of the grid notation
+const STEP_HIT: char = 'x';
+const STEP_ACCENT: char = 'X';
+const STEP_REST: char = '.';
+const STEP_HOLD: char = '-';
+// Ignored characters so long patterns can be split into bars: "x...|x..."
+const STEP_SEPARATORS: [char; 2] = ['|', ' '];
+
+/// Amplitude used for accented steps when the pattern doesn't set one
+const DEFAULT_ACCENT: f64 = 1.0;
+
+fn default_repeat() -> u32 {
+    1
+}
+
+/// Compact step-sequencer notation, e.g. `"x...x...x.x.x..."`
+/// `x` plays a step, `X` plays an accented step, `.` rests and `-` holds the previous step
+#[derive(Debug, Deserialize, Clone)]
+pub struct StepPattern<I> {
+    steps: String,
+    step: f64, // length of one step in beats
+    #[serde(default)]
+    start_time: f64, // in beats
+    #[serde(default = "default_repeat")]
+    repeat: u32,
+    amplitude: f64,
+    accent: Option<f64>, // amplitude of accented steps
+    #[serde(flatten)]
+    instrument: I,
+}
+
+/// Instrument of a pattern in the top-level `patterns` array
+#[derive(Debug, Deserialize, Clone)]
+pub struct PitchedInstrument {
+    #[serde(flatten)]
+    pitch: Pitch,
+}
+
+/// Instrument of a pattern inside a percussion track
+#[derive(Debug, Deserialize, Clone)]
+pub struct DrumInstrument {
+    hit: DrumHit,
+}
+
+/// A single played step after expanding the grid
+struct PatternStep {
+    start_time: f64, // in beats
+    length: f64,     // in beats, including held steps
+    amplitude: f64,
+}
+
+impl<I> StepPattern<I> {
+    /// Checks the grid and the pattern's own fields, returning all problems found
+    pub fn check_steps(&self) -> Vec<FieldProblem> {
+        let mut problems = Vec::new();
+        if !self.step.is_finite() || self.step <= 0.0 {
+            problems.push((
+                "step",
+                OrchestratorError::InvalidPattern(format!(
+                    "Step length must be greater than 0 beats, got {}",
+                    self.step
+                )),
+            ));
+        }
+        if !self.start_time.is_finite() || self.start_time < 0.0 {
+            problems.push((
+                "start_time",
+                OrchestratorError::InvalidPattern(format!(
+                    "Start time must be a finite number of beats, 0 or more, got {}",
+                    self.start_time
+                )),
+            ));
+        }
+        if self.repeat == 0 {
+            problems.push((
+                "repeat",
+                OrchestratorError::InvalidPattern(format!(
+                    "Pattern \"{}\" must repeat at least once",
+                    self.steps
+                )),
+            ));
+        }
+        if let Err(e) = self.parse_cycle() {
+            problems.push(("steps", e));
+        }
+        if let Err(e) = validate_amplitude(self.amplitude) {
+            problems.push(("amplitude", e));
+        }
+        if let Some(Err(e)) = self.accent.map(validate_amplitude) {
+            problems.push(("accent", e));
+        }
+        problems
+    }
+
+    // Parses a single cycle of the grid into played steps
+    fn parse_cycle(&self) -> Result<Vec<PatternStep>, OrchestratorError> {
+        let accent = self.accent.unwrap_or(DEFAULT_ACCENT);
+
+        let mut cycle: Vec<PatternStep> = Vec::new();
+        // Whether the previous cell was played or held, so a hold can extend it
+        let mut sounding = false;
+        for (index, cell) in self.cells().enumerate() {
+            match cell {
+                STEP_HIT | STEP_ACCENT => {
+                    cycle.push(PatternStep {
+                        start_time: index as f64 * self.step,
+                        length: self.step,
+                        amplitude: if cell == STEP_ACCENT {
+                            accent
+                        } else {
+                            self.amplitude
+                        },
+                    });
+                    sounding = true;
+                }
+                STEP_REST => sounding = false,
+                STEP_HOLD => match cycle.last_mut() {
+                    Some(previous) if sounding => previous.length += self.step,
+                    _ => {
+                        return Err(OrchestratorError::InvalidPattern(format!(
+                            "Hold '{}' at step {} of \"{}\" doesn't follow a played step",
+                            STEP_HOLD, index, self.steps
+                        )));
+                    }
+                },
+                other => {
+                    return Err(OrchestratorError::InvalidPattern(format!(
+                        "Unknown step '{}' in \"{}\", expected one of x X . -",
+                        other, self.steps
+                    )));
+                }
+            }
+        }
+        Ok(cycle)
+    }
+
+    /// End of the last repeat in beats, trailing rests included
+    pub fn end_in_beats(&self) -> f64 {
+        self.start_time + self.cells().count() as f64 * self.step * self.repeat as f64
+    }
+
+    fn cells(&self) -> impl Iterator<Item = char> + '_ {
+        self.steps.chars().filter(|c| !STEP_SEPARATORS.contains(c))
+    }
+
+    fn expand_steps(&self) -> Result<Vec<PatternStep>, OrchestratorError> {
+        if let Some((_, error)) = self.check_steps().into_iter().next() {
+            return Err(error);
+        }
+        let cycle = self.parse_cycle()?;
+        let pattern_length = self.cells().count() as f64 * self.step;
+
+        // Shift the cycle for each repeat
+        let mut steps = Vec::with_capacity(cycle.len() * self.repeat as usize);
+        for repetition in 0..self.repeat {
+            let offset = self.start_time + repetition as f64 * pattern_length;
+            steps.extend(cycle.iter().map(|step| PatternStep {
+                start_time: offset + step.start_time,
+                length: step.length,
+                amplitude: step.amplitude,
+            }));
+        }
+        Ok(steps)
+    }
+}
+
+impl StepPattern<PitchedInstrument> {
+    /// Checks the grid and the cents offset of the pattern, returning all problems found
+    /// The pitch depends on the tuning and is checked with `validate_pitch`.
+    pub fn check(&self) -> Vec<FieldProblem> {
+        let mut problems = self.check_steps();
+        if let Err(e) = validate_cents(self.instrument.pitch.cents()) {
+            problems.push(("cents", e));
+        }
+        problems
+    }
+
+    pub fn pitch(&self) -> &Pitch {
+        &self.instrument.pitch
+    }
+
+    pub fn expand(&self) -> Result<Vec<TimelineNote>, OrchestratorError> {
+        Ok(self
+            .expand_steps()?
+            .into_iter()
+            .map(|step| {
+                TimelineNote::new(
+                    self.instrument.pitch,
+                    step.start_time,
+                    step.length,
+                    step.amplitude,
+                )
+            })
+            .collect())
+    }
+}
+
+impl StepPattern<DrumInstrument> {
+    // Drum hits ring for their natural length, so held steps don't change them
+    pub fn expand(&self) -> Result<Vec<PercussionNote>, OrchestratorError> {
+        Ok(self
+            .expand_steps()?
+            .into_iter()
+            .map(|step| {
+                PercussionNote::new(self.instrument.hit, step.start_time, None, step.amplitude)
+            })
+            .collect())
+    }
+}
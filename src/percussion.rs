@@ -0,0 +1,292 @@
+use crate::errors::OrchestratorError;
+use crate::noise::{DEFAULT_SEED, NoiseColor, NoiseGenerator};
+use crate::pattern::{DrumInstrument, StepPattern};
+use crate::progress::ProgressTracker;
+use serde::Deserialize;
+use std::f64::consts::PI;
+
+// Kick drum: a sine whose pitch drops quickly from the start to the end frequency
+const KICK_START_FREQUENCY: f64 = 150.0;
+const KICK_END_FREQUENCY: f64 = 45.0;
+const KICK_SWEEP_TIME: f64 = 0.04; // seconds for the pitch sweep to mostly settle
+const KICK_DECAY: f64 = 0.15;
+
+// Snare drum: a short tonal body under a longer burst of noise
+const SNARE_BODY_FREQUENCY: f64 = 185.0;
+const SNARE_BODY_DECAY: f64 = 0.05;
+const SNARE_NOISE_DECAY: f64 = 0.08;
+
+const HAT_DECAY: f64 = 0.02;
+
+// Hand clap: a few quick noise bursts followed by a diffuse tail
+const CLAP_BURSTS: f64 = 3.0;
+const CLAP_BURST_SPACING: f64 = 0.01;
+const CLAP_BURST_DECAY: f64 = 0.003;
+const CLAP_TAIL_DECAY: f64 = 0.08;
+
+// Fade applied when a hit is cut short by its duration, to avoid clicks
+const CHOKE_FADE: f64 = 0.005;
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DrumHit {
+    Kick,
+    Snare,
+    Hat,
+    Clap,
+}
+
+impl DrumHit {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DrumHit::Kick => "kick",
+            DrumHit::Snare => "snare",
+            DrumHit::Hat => "hat",
+            DrumHit::Clap => "clap",
+        }
+    }
+
+    // Length in seconds after which the hit has decayed to silence
+    pub fn natural_length(&self) -> f64 {
+        match self {
+            DrumHit::Kick => 0.6,
+            DrumHit::Snare => 0.35,
+            DrumHit::Hat => 0.12,
+            DrumHit::Clap => 0.4,
+        }
+    }
+
+    fn default_noise(&self) -> NoiseColor {
+        match self {
+            DrumHit::Clap => NoiseColor::Pink,
+            _ => NoiseColor::White,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PercussionNote {
+    hit: DrumHit,
+    start_time: f64,       // in beats
+    duration: Option<f64>, // in beats, chokes the hit early when shorter than its natural length
+    amplitude: f64,
+}
+
+impl PercussionNote {
+    pub fn new(hit: DrumHit, start_time: f64, duration: Option<f64>, amplitude: f64) -> Self {
+        Self {
+            hit,
+            start_time,
+            duration,
+            amplitude,
+        }
+    }
+
+    pub fn amplitude(&self) -> f64 {
+        self.amplitude
+    }
+
+    pub fn hit(&self) -> DrumHit {
+        self.hit
+    }
+
+    pub fn start_time(&self) -> f64 {
+        self.start_time
+    }
+
+    pub fn duration(&self) -> Option<f64> {
+        self.duration
+    }
+
+    // Length of the hit in seconds, taking an optional choke into account
+    pub fn length_in_seconds(&self, seconds_per_beat: f64) -> f64 {
+        let natural = self.hit.natural_length();
+        match self.duration {
+            Some(duration) => natural.min(duration * seconds_per_beat),
+            None => natural,
+        }
+    }
+
+    pub fn shifted(&self, beats: f64) -> Self {
+        Self {
+            start_time: self.start_time + beats,
+            ..self.clone()
+        }
+    }
+
+    // End in beats, when the hit stops ringing or is choked
+    pub fn end_in_beats(&self, seconds_per_beat: f64) -> f64 {
+        self.start_time + self.length_in_seconds(seconds_per_beat) / seconds_per_beat
+    }
+
+    pub fn end_in_seconds(&self, seconds_per_beat: f64) -> f64 {
+        self.start_time * seconds_per_beat + self.length_in_seconds(seconds_per_beat)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PercussionTrack {
+    #[serde(default)]
+    notes: Vec<PercussionNote>,
+    #[serde(default)]
+    patterns: Vec<StepPattern<DrumInstrument>>,
+    seed: Option<u64>,
+    noise: Option<NoiseColor>, // overrides the noise color of every hit in the track
+}
+
+impl PercussionTrack {
+    pub fn notes(&self) -> &[PercussionNote] {
+        &self.notes
+    }
+
+    pub fn patterns(&self) -> &[StepPattern<DrumInstrument>] {
+        &self.patterns
+    }
+
+    // Turns the track's step patterns into regular hits
+    pub fn expand_patterns(&mut self) -> Result<(), OrchestratorError> {
+        for pattern in self.patterns.drain(..) {
+            self.notes.extend(pattern.expand()?);
+        }
+        Ok(())
+    }
+
+    pub fn shifted(&self, beats: f64) -> Self {
+        Self {
+            notes: self.notes.iter().map(|note| note.shifted(beats)).collect(),
+            ..self.clone()
+        }
+    }
+
+    pub fn end_in_beats(&self, seconds_per_beat: f64) -> f64 {
+        self.notes
+            .iter()
+            .map(|note| note.end_in_beats(seconds_per_beat))
+            .fold(0.0, f64::max)
+    }
+
+    // End of the grids of the step patterns not expanded yet
+    pub fn patterns_end_in_beats(&self) -> f64 {
+        self.patterns
+            .iter()
+            .map(StepPattern::end_in_beats)
+            .fold(0.0, f64::max)
+    }
+
+    pub fn end_in_seconds(&self, seconds_per_beat: f64) -> f64 {
+        self.notes
+            .iter()
+            .map(|note| note.end_in_seconds(seconds_per_beat))
+            .fold(0.0, f64::max)
+    }
+
+    // Number of samples the hits of the track generate, for progress reporting
+    pub fn samples_to_mix(&self, seconds_per_beat: f64, sample_rate: u32) -> usize {
+        self.notes
+            .iter()
+            .map(|note| (note.length_in_seconds(seconds_per_beat) * sample_rate as f64) as usize)
+            .sum()
+    }
+
+    // Renders every hit of the track and adds it onto the timeline
+    pub fn mix_into(
+        &self,
+        timeline: &mut [f64],
+        seconds_per_beat: f64,
+        sample_rate: u32,
+        gain: f64,
+        progress: &mut ProgressTracker,
+    ) {
+        let seed = self.seed.unwrap_or(DEFAULT_SEED);
+
+        for (index, note) in self.notes.iter().enumerate() {
+            let color = self.noise.unwrap_or(note.hit.default_noise());
+            // Every hit gets its own stream so editing one hit doesn't change the others
+            let noise = NoiseGenerator::new(color, seed.wrapping_add(index as u64));
+            let mut voice = DrumVoice::new(note.hit, sample_rate, noise);
+
+            let start_sample = (note.start_time * seconds_per_beat * sample_rate as f64) as usize;
+            let length = note.length_in_seconds(seconds_per_beat);
+            let samples_for_this_hit = (length * sample_rate as f64) as usize;
+            let choked = length < note.hit.natural_length();
+
+            for i in 0..samples_for_this_hit {
+                let current_sample_index = start_sample + i;
+                if current_sample_index >= timeline.len() {
+                    break;
+                }
+                let mut sample = voice.sample() * note.amplitude * gain;
+                if choked {
+                    let remaining = (samples_for_this_hit - i) as f64 / sample_rate as f64;
+                    sample *= (remaining / CHOKE_FADE).min(1.0);
+                }
+                timeline[current_sample_index] += sample;
+            }
+            progress.advance(1, samples_for_this_hit);
+        }
+    }
+}
+
+/// Synthesises a single drum hit sample by sample
+pub struct DrumVoice {
+    hit: DrumHit,
+    sample_rate: u32,
+    noise: NoiseGenerator,
+    sample_index: u32,
+    phase: f64,          // accumulated phase for the swept kick sine
+    previous_noise: f64, // last noise sample, used for the hat's high-pass
+}
+
+impl DrumVoice {
+    pub fn new(hit: DrumHit, sample_rate: u32, noise: NoiseGenerator) -> Self {
+        Self {
+            hit,
+            sample_rate,
+            noise,
+            sample_index: 0,
+            phase: 0.0,
+            previous_noise: 0.0,
+        }
+    }
+
+    pub fn sample(&mut self) -> f64 {
+        let t = self.sample_index as f64 / self.sample_rate as f64;
+        self.sample_index += 1;
+
+        match self.hit {
+            DrumHit::Kick => {
+                // Frequency changes every sample, so the phase has to be accumulated
+                let frequency = KICK_END_FREQUENCY
+                    + (KICK_START_FREQUENCY - KICK_END_FREQUENCY) * (-t / KICK_SWEEP_TIME).exp();
+                self.phase = (self.phase + frequency / self.sample_rate as f64).fract();
+                (2.0 * PI * self.phase).sin() * decay(t, KICK_DECAY)
+            }
+            DrumHit::Snare => {
+                let body = (2.0 * PI * SNARE_BODY_FREQUENCY * t).sin() * decay(t, SNARE_BODY_DECAY);
+                let noise = self.noise.sample() * decay(t, SNARE_NOISE_DECAY);
+                0.5 * body + 0.7 * noise
+            }
+            DrumHit::Hat => {
+                // First difference is a cheap high-pass that keeps only the sizzle
+                let noise = self.noise.sample();
+                let high_passed = 0.5 * (noise - self.previous_noise);
+                self.previous_noise = noise;
+                high_passed * decay(t, HAT_DECAY)
+            }
+            DrumHit::Clap => {
+                let bursts_end = CLAP_BURSTS * CLAP_BURST_SPACING;
+                let envelope = if t < bursts_end {
+                    decay(t % CLAP_BURST_SPACING, CLAP_BURST_DECAY)
+                } else {
+                    decay(t - bursts_end, CLAP_TAIL_DECAY)
+                };
+                self.noise.sample() * envelope
+            }
+        }
+    }
+}
+
+// Short exponential envelope, falls to about 37% after `time_constant` seconds
+fn decay(t: f64, time_constant: f64) -> f64 {
+    (-t / time_constant).exp()
+}
@@ -0,0 +1,157 @@
+use crate::noise::{DEFAULT_SEED, Rng};
+use crate::oscillator::Oscillator;
+use serde::Deserialize;
+
+/// Seconds a string rings before falling 60 dB when it isn't damped at all
+const LONGEST_RING: f64 = 10.0;
+/// Level 60 dB below the start, where a string counts as decayed
+const DECAYED: f64 = 0.001;
+// Shortest fractional delay the tuning allpass is given, it gets unstable closer to 0
+const MIN_ALLPASS_DELAY: f64 = 0.1;
+
+/// Plucked string voice, played with the extended Karplus-Strong algorithm
+/// A burst of noise circulates through a delay line one period long, losing energy
+/// and high frequencies on every trip.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PluckVoice {
+    #[serde(default = "default_damping")]
+    pub damping: f64, // 0.0 rings longest, 1.0 dies after the first period
+    #[serde(default = "default_brightness")]
+    pub brightness: f64, // 0.0 is the mellow classic string, 1.0 keeps every harmonic
+    pub pick_position: Option<f64>, // along the string, 0.5 plucks the middle
+    #[serde(default = "default_release")]
+    pub release: f64, // seconds to fall 60 dB once the note is released
+    pub seed: Option<u64>,          // seed for the noise, mixed with each note's start and pitch
+}
+
+fn default_damping() -> f64 {
+    0.5
+}
+
+fn default_brightness() -> f64 {
+    0.5
+}
+
+fn default_release() -> f64 {
+    0.1
+}
+
+impl PluckVoice {
+    /// Seconds the string rings for before falling 60 dB while the note is held
+    pub fn ring(&self) -> f64 {
+        LONGEST_RING * (1.0 - self.damping)
+    }
+}
+
+/// Plays a plucked string, damping it once the note is released
+pub struct PluckOscillator {
+    pub amplitude: f64,
+    string: Vec<f64>, // delay line, one period minus the delay of the filters
+    position: usize,
+    smoothing: f64, // weight of the previous sample in the loop's lowpass
+    previous: f64,
+    allpass: f64, // coefficient of the allpass tuning the fractional part of the period
+    allpass_input: f64,
+    allpass_output: f64,
+    gain: f64,         // kept on each trip along the string while the note is held
+    release_gain: f64, // kept on each trip once the note is released
+    note_off: u32,     // sample index of the release
+}
+
+impl PluckOscillator {
+    // `duration` is the time in seconds before the note is released, `start_time` in beats
+    // varies the noise
+    pub fn new(
+        frequency: f64,
+        amplitude: f64,
+        sample_rate: u32,
+        voice: PluckVoice,
+        duration: f64,
+        start_time: f64,
+    ) -> Self {
+        let period = sample_rate as f64 / frequency;
+        // The lowpass delays by `smoothing` samples and the allpass by the rest of the
+        // fraction, the delay line makes up the whole samples
+        let smoothing = 0.5 * (1.0 - voice.brightness);
+        let length = ((period - smoothing - MIN_ALLPASS_DELAY).floor() as usize).max(1);
+        let fraction = period - smoothing - length as f64;
+        let allpass = (1.0 - fraction) / (1.0 + fraction);
+
+        let seed = note_seed(voice.seed.unwrap_or(DEFAULT_SEED), start_time, frequency);
+        let string = excitation(length, voice.pick_position, seed);
+        // Loss per trip along the string, so it falls 60 dB over the ring time
+        let loss = |seconds: f64| DECAYED.powf(1.0 / (frequency * seconds));
+        Self {
+            amplitude,
+            string,
+            position: 0,
+            smoothing,
+            previous: 0.0,
+            allpass,
+            allpass_input: 0.0,
+            allpass_output: 0.0,
+            gain: loss(voice.ring()),
+            release_gain: loss(voice.release),
+            note_off: (duration * sample_rate as f64) as u32,
+        }
+    }
+}
+
+impl Oscillator for PluckOscillator {
+    fn sample(&mut self, sample_index: u32) -> f64 {
+        let current = self.string[self.position];
+        let lowpass = (1.0 - self.smoothing) * current + self.smoothing * self.previous;
+        self.previous = current;
+        let allpass =
+            self.allpass * lowpass + self.allpass_input - self.allpass * self.allpass_output;
+        self.allpass_input = lowpass;
+        self.allpass_output = allpass;
+
+        let gain = if sample_index < self.note_off {
+            self.gain
+        } else {
+            self.release_gain
+        };
+        self.string[self.position] = gain * allpass;
+        self.position = (self.position + 1) % self.string.len();
+        self.amplitude * current
+    }
+}
+
+// Seed of a note's noise, from when it starts and its pitch so other notes don't change it
+fn note_seed(seed: u64, start_time: f64, frequency: f64) -> u64 {
+    let pitch = Rng::new(frequency.to_bits()).next_u64();
+    seed ^ Rng::new(start_time.to_bits() ^ pitch).next_u64()
+}
+
+// Noise burst that starts the string, centred and peaking at 1.0
+// Plucking at a position cancels the harmonics that have a node there.
+fn excitation(length: usize, pick_position: Option<f64>, seed: u64) -> Vec<f64> {
+    let mut rng = Rng::new(seed);
+    let noise: Vec<f64> = (0..length).map(|_| rng.next_bipolar()).collect();
+    let mut burst = match pick_position {
+        Some(position) => {
+            let offset = (position * length as f64).round() as usize;
+            (0..length)
+                .map(|index| {
+                    noise[index] - index.checked_sub(offset).map_or(0.0, |back| noise[back])
+                })
+                .collect()
+        }
+        None => noise,
+    };
+    // The lowpass passes a DC offset, which would then ring as long as the string
+    let mean = burst.iter().sum::<f64>() / length as f64;
+    let peak = burst
+        .iter()
+        .map(|value| (value - mean).abs())
+        .fold(0.0, f64::max);
+    for value in burst.iter_mut() {
+        *value = if peak > 0.0 {
+            (*value - mean) / peak
+        } else {
+            0.0
+        };
+    }
+    burst
+}
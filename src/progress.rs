@@ -0,0 +1,94 @@
+use serde::Serialize;
+use std::time::{Duration, Instant};
+
+// Minimum time between two progress events, the first and last event are always sent
+const REPORT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How far a render has got, sent to progress listeners as it runs
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Progress {
+    pub notes_done: usize, // notes and drum hits mixed so far
+    pub notes_total: usize,
+    pub samples_mixed: usize, // note samples generated so far, overlapping notes each count
+    pub samples_total: usize,
+    pub elapsed: f64,     // in seconds
+    pub eta: Option<f64>, // estimated seconds left, unknown until some work is done
+    pub finished: bool,
+}
+
+impl Progress {
+    /// Share of the work done, from 0.0 to 1.0
+    pub fn fraction(&self) -> f64 {
+        if self.samples_total == 0 {
+            return if self.finished { 1.0 } else { 0.0 };
+        }
+        (self.samples_mixed as f64 / self.samples_total as f64).min(1.0)
+    }
+}
+
+/// Counts the work done by a render and passes progress events to a callback
+/// Events are throttled so listeners aren't flooded on renders with many short notes.
+pub struct ProgressTracker<'a> {
+    on_progress: &'a mut dyn FnMut(&Progress),
+    progress: Progress,
+    started: Instant,
+    last_report: Instant,
+}
+
+impl<'a> ProgressTracker<'a> {
+    pub fn new(
+        notes_total: usize,
+        samples_total: usize,
+        on_progress: &'a mut dyn FnMut(&Progress),
+    ) -> Self {
+        let now = Instant::now();
+        let progress = Progress {
+            notes_done: 0,
+            notes_total,
+            samples_mixed: 0,
+            samples_total,
+            elapsed: 0.0,
+            eta: None,
+            finished: false,
+        };
+        on_progress(&progress);
+        ProgressTracker {
+            on_progress,
+            progress,
+            started: now,
+            last_report: now,
+        }
+    }
+
+    /// Records that `notes` more notes, made of `samples` samples, have been mixed
+    pub fn advance(&mut self, notes: usize, samples: usize) {
+        self.progress.notes_done += notes;
+        self.progress.samples_mixed += samples;
+        if self.last_report.elapsed() >= REPORT_INTERVAL {
+            self.report();
+        }
+    }
+
+    /// Sends the final event once all the work is done
+    pub fn finish(mut self) {
+        self.progress.notes_done = self.progress.notes_total;
+        self.progress.samples_mixed = self.progress.samples_total;
+        self.progress.finished = true;
+        self.report();
+    }
+
+    fn report(&mut self) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let fraction = self.progress.fraction();
+        self.progress.elapsed = elapsed;
+        self.progress.eta = if self.progress.finished {
+            Some(0.0)
+        } else if fraction > 0.0 {
+            Some(elapsed * (1.0 - fraction) / fraction)
+        } else {
+            None
+        };
+        self.last_report = Instant::now();
+        (self.on_progress)(&self.progress);
+    }
+}
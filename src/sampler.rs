@@ -0,0 +1,383 @@
+use crate::errors::OrchestratorError;
+use crate::oscillator::Oscillator;
+use crate::validation::validate_zone_count;
+use serde::Deserialize;
+use std::ops::{Range, RangeInclusive};
+use std::sync::Arc;
+
+// MIDI key of A4 and its frequency, which recordings are tuned to
+const MIDI_A4: f64 = 69.0;
+const A4_FREQUENCY: f64 = 440.0;
+// A key range holds pitches up to a quarter tone outside its ends, so notes of other
+// tunings still land in a zone
+const RANGE_TOLERANCE: f64 = 1.0 / 24.0; // in octaves
+const CENTS_PER_OCTAVE: f64 = 1200.0;
+// Highest MIDI velocity, which a note of amplitude 1.0 plays at
+const MAX_VELOCITY: f64 = 127.0;
+// Levels envelopes fall by over their decay and release times, 100 dB
+const ENVELOPE_RANGE: f64 = 1e-5;
+
+/// Frequency of a MIDI key in equal temperament with A4 at 440 Hz, keys may be fractional
+pub fn midi_frequency(key: f64) -> f64 {
+    A4_FREQUENCY * 2_f64.powf((key - MIDI_A4) / 12.0)
+}
+
+/// MIDI velocity of a note, from its amplitude between 0.0 and 1.0
+pub fn midi_velocity(amplitude: f64) -> u8 {
+    (amplitude * MAX_VELOCITY).round().clamp(0.0, MAX_VELOCITY) as u8
+}
+
+/// How a sample is read between its samples when it plays at another speed
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interpolation {
+    Nearest, // the closest sample, gritty like early samplers
+    Linear,
+    #[default]
+    Cubic, // Hermite curve through the four closest samples
+}
+
+impl Interpolation {
+    pub fn name(self) -> &'static str {
+        match self {
+            Interpolation::Nearest => "nearest",
+            Interpolation::Linear => "linear",
+            Interpolation::Cubic => "cubic",
+        }
+    }
+}
+
+/// Volume envelope of a zone, shaped as SoundFonts define them
+/// Decays and releases fall at a steady rate in decibels, their times are how long
+/// falling 100 dB takes.
+#[derive(Debug, Clone, Copy)]
+pub struct VolumeEnvelope {
+    pub delay: f64, // in seconds, silence before the attack
+    pub attack: f64,
+    pub hold: f64, // in seconds, at full level before the decay
+    pub decay: f64,
+    pub sustain: f64, // level 0.0 to 1.0
+    pub release: f64,
+}
+
+impl VolumeEnvelope {
+    // Level at a time in seconds, for a note released at `note_off` seconds
+    fn level(&self, time: f64, note_off: f64) -> f64 {
+        if time < note_off {
+            return self.held(time);
+        }
+        self.held(note_off) * fall(time - note_off, self.release)
+    }
+
+    // Level while the note is held
+    fn held(&self, time: f64) -> f64 {
+        let time = time - self.delay;
+        if time < 0.0 {
+            return 0.0;
+        }
+        if time < self.attack {
+            return time / self.attack;
+        }
+        fall(time - self.attack - self.hold, self.decay).max(self.sustain)
+    }
+}
+
+// Level after falling for `time` seconds at 100 dB per `length` seconds
+fn fall(time: f64, length: f64) -> f64 {
+    if time <= 0.0 {
+        1.0
+    } else if length > 0.0 {
+        ENVELOPE_RANGE.powf(time / length)
+    } else {
+        0.0
+    }
+}
+
+/// One recording of a sampler and the notes it plays
+#[derive(Debug, Clone)]
+pub struct Zone {
+    samples: Arc<Vec<f64>>, // shared by every note the zone plays
+    sample_rate: u32,
+    root: Option<f64>, // in Hz, pitch of the recording, which then plays at any speed
+    keys: Range<f64>,  // in Hz, pitches the zone plays
+    sample_loop: Option<Range<usize>>, // in samples, repeated while the note sounds
+    velocities: RangeInclusive<u8>, // MIDI velocities the zone plays
+    tracking: f64,     // how far the pitch follows the note, 0.0 plays every note alike
+    tune: f64,         // in cents
+    gain: f64,
+    envelope: Option<VolumeEnvelope>, // the timeline's ADSR shapes zones without one
+    release_from_loop: bool,          // leaves the loop at note-off and plays to the end
+}
+
+impl Zone {
+    // Loops must lie within the samples and hold at least one
+    pub fn new(
+        samples: Arc<Vec<f64>>,
+        sample_rate: u32,
+        root: Option<f64>,
+        keys: Range<f64>,
+        sample_loop: Option<Range<usize>>,
+    ) -> Result<Self, OrchestratorError> {
+        if let Some(range) = &sample_loop
+            && (range.start >= range.end || range.end > samples.len())
+        {
+            return Err(OrchestratorError::InvalidSampler(format!(
+                "the loop {}..{} must hold at least one of the {} samples of its file",
+                range.start,
+                range.end,
+                samples.len()
+            )));
+        }
+        Ok(Zone {
+            samples,
+            sample_rate,
+            root,
+            keys,
+            sample_loop,
+            velocities: 0..=MAX_VELOCITY as u8,
+            tracking: 1.0,
+            tune: 0.0,
+            gain: 1.0,
+            envelope: None,
+            release_from_loop: false,
+        })
+    }
+
+    pub fn with_velocities(mut self, velocities: RangeInclusive<u8>) -> Self {
+        self.velocities = velocities;
+        self
+    }
+
+    // `tracking` is the share of the note's distance from the root the pitch moves by
+    pub fn with_tuning(mut self, tracking: f64, cents: f64) -> Self {
+        self.tracking = tracking;
+        self.tune = cents;
+        self
+    }
+
+    pub fn with_gain(mut self, gain: f64) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    pub fn with_envelope(mut self, envelope: VolumeEnvelope) -> Self {
+        self.envelope = Some(envelope);
+        self
+    }
+
+    pub fn with_release_from_loop(mut self) -> Self {
+        self.release_from_loop = true;
+        self
+    }
+
+    // Samples of the recording read per second of playback
+    fn speed(&self, frequency: f64) -> f64 {
+        let pitch = self
+            .root
+            .map_or(1.0, |root| (frequency / root).powf(self.tracking));
+        pitch * 2_f64.powf(self.tune / CENTS_PER_OCTAVE) * self.sample_rate as f64
+    }
+
+    // Octaves between a pitch and the key range, 0.0 when the range holds it
+    fn distance(&self, frequency: f64) -> f64 {
+        let low = (self.keys.start / frequency).log2() - RANGE_TOLERANCE;
+        let high = (frequency / self.keys.end).log2() - RANGE_TOLERANCE;
+        low.max(high).max(0.0)
+    }
+}
+
+/// Plays recordings at the pitch of each note, choosing one per note from its zones
+/// Layered samplers play every zone holding the note at once, as SoundFonts do.
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    zones: Vec<Zone>,
+    interpolation: Interpolation,
+    layered: bool,
+}
+
+impl Sampler {
+    pub fn new(zones: Vec<Zone>, interpolation: Interpolation) -> Result<Self, OrchestratorError> {
+        validate_zone_count(zones.len())?;
+        Ok(Sampler {
+            zones,
+            interpolation,
+            layered: false,
+        })
+    }
+
+    pub fn with_layers(mut self) -> Self {
+        self.layered = true;
+        self
+    }
+
+    pub fn zones(&self) -> &[Zone] {
+        &self.zones
+    }
+
+    pub fn interpolation(&self) -> Interpolation {
+        self.interpolation
+    }
+
+    /// Seconds notes keep sounding after note-off, `None` when no zone has an envelope
+    pub fn release(&self) -> Option<f64> {
+        self.zones
+            .iter()
+            .filter_map(|zone| zone.envelope)
+            .map(|envelope| envelope.release)
+            .reduce(f64::max)
+    }
+
+    /// Zones playing a note, the first whose key range holds its pitch or every one when
+    /// layered, and otherwise the one whose range is closest
+    /// Only zones playing the note's velocity count, unless none does.
+    pub fn zones_for(&self, frequency: f64, velocity: u8) -> Vec<&Zone> {
+        let mut candidates: Vec<&Zone> = self
+            .zones
+            .iter()
+            .filter(|zone| zone.velocities.contains(&velocity))
+            .collect();
+        if candidates.is_empty() {
+            candidates = self.zones.iter().collect();
+        }
+        let holding = candidates
+            .iter()
+            .copied()
+            .filter(|zone| zone.distance(frequency) == 0.0);
+        let zones: Vec<&Zone> = if self.layered {
+            holding.collect()
+        } else {
+            holding.take(1).collect()
+        };
+        if !zones.is_empty() {
+            return zones;
+        }
+        candidates
+            .into_iter()
+            .min_by(|a, b| a.distance(frequency).total_cmp(&b.distance(frequency)))
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Plays the zones of a sampler holding a note, resampled to its pitch
+/// Zones without a root play at their recorded speed whatever the note, as one-shots do.
+pub struct SamplerOscillator {
+    pub amplitude: f64,
+    layers: Vec<Layer>,
+    interpolation: Interpolation,
+    sample_rate: u32,
+    note_off: u32, // sample index of the release
+}
+
+// A zone being played and where in its recording the note is
+struct Layer {
+    zone: Zone,
+    step: f64,     // samples of the recording read per output sample
+    position: f64, // in samples of the recording
+}
+
+impl SamplerOscillator {
+    // `duration` is the time in seconds before the note is released, `velocity` the
+    // note's amplitude from 0.0 to 1.0
+    pub fn new(
+        frequency: f64,
+        amplitude: f64,
+        velocity: f64,
+        sample_rate: u32,
+        sampler: &Sampler,
+        duration: f64,
+    ) -> Self {
+        let layers = sampler
+            .zones_for(frequency, midi_velocity(velocity))
+            .into_iter()
+            .map(|zone| Layer {
+                step: zone.speed(frequency) / sample_rate as f64,
+                zone: zone.clone(),
+                position: 0.0,
+            })
+            .collect();
+        Self {
+            amplitude,
+            layers,
+            interpolation: sampler.interpolation,
+            sample_rate,
+            note_off: (duration * sample_rate as f64) as u32,
+        }
+    }
+}
+
+impl Layer {
+    // Sample of the recording at an index, following the loop and silent past the end
+    fn at(&self, index: isize, looping: bool) -> f64 {
+        let samples = &self.zone.samples;
+        let index = match &self.zone.sample_loop {
+            Some(range) if looping && index >= range.end as isize => {
+                let length = range.len() as isize;
+                range.start as isize + (index - range.start as isize) % length
+            }
+            _ => index,
+        };
+        if index < 0 {
+            return 0.0;
+        }
+        samples.get(index as usize).copied().unwrap_or(0.0)
+    }
+
+    fn sample(&mut self, interpolation: Interpolation, looping: bool) -> f64 {
+        let index = self.position.floor() as isize;
+        let fraction = self.position - index as f64;
+        let value = match interpolation {
+            Interpolation::Nearest => self.at(self.position.round() as isize, looping),
+            Interpolation::Linear => {
+                let (current, next) = (self.at(index, looping), self.at(index + 1, looping));
+                current + (next - current) * fraction
+            }
+            Interpolation::Cubic => hermite(
+                [
+                    self.at(index - 1, looping),
+                    self.at(index, looping),
+                    self.at(index + 1, looping),
+                    self.at(index + 2, looping),
+                ],
+                fraction,
+            ),
+        };
+
+        self.position += self.step;
+        // Wrapped here as well, so the position stays small on long notes
+        if let Some(range) = &self.zone.sample_loop
+            && looping
+            && self.position >= range.end as f64
+        {
+            self.position =
+                range.start as f64 + (self.position - range.start as f64) % range.len() as f64;
+        }
+        value
+    }
+}
+
+impl Oscillator for SamplerOscillator {
+    fn sample(&mut self, sample_index: u32) -> f64 {
+        let released = sample_index >= self.note_off;
+        let time = sample_index as f64 / self.sample_rate as f64;
+        let note_off = self.note_off as f64 / self.sample_rate as f64;
+        let mut sum = 0.0;
+        for layer in &mut self.layers {
+            let looping = !(released && layer.zone.release_from_loop);
+            let level = layer
+                .zone
+                .envelope
+                .map_or(1.0, |envelope| envelope.level(time, note_off));
+            sum += layer.zone.gain * level * layer.sample(self.interpolation, looping);
+        }
+        self.amplitude * sum
+    }
+}
+
+// Catmull-Rom spline through four evenly spaced points, at `t` between the middle two
+fn hermite([before, current, next, after]: [f64; 4], t: f64) -> f64 {
+    let c1 = 0.5 * (next - before);
+    let c2 = before - 2.5 * current + 2.0 * next - 0.5 * after;
+    let c3 = 0.5 * (after - before) + 1.5 * (current - next);
+    ((c3 * t + c2) * t + c1) * t + current
+}
@@ -0,0 +1,342 @@
+use crate::errors::OrchestratorError;
+
+const CENTS_PER_OCTAVE: f64 = 1200.0;
+
+/// Notes per octave of the built-in temperaments
+const CHROMATIC_NOTES: usize = 12;
+/// Key of the first scale degree when no keyboard mapping is given, C4 for twelve-note octaves
+const DEFAULT_MIDDLE_OCTAVE: i64 = 4;
+/// Steps from the first scale degree to the reference key, A4 for twelve-note octaves
+const DEFAULT_REFERENCE_STEPS: i64 = 9;
+
+// Marks a comment line in Scala files
+const COMMENT: char = '!';
+// Marks an unmapped key in a keyboard mapping
+const UNMAPPED: &str = "x";
+
+/// Pitches of one period of a scale, usually an octave
+/// Read from a Scala `.scl` file or built from one of the named temperaments.
+#[derive(Debug, Clone)]
+pub struct Scale {
+    description: String,
+    degrees: Vec<f64>, // cents above the first degree, the last one is the period
+}
+
+impl Scale {
+    /// Parses the text of a Scala `.scl` file
+    pub fn parse(text: &str) -> Result<Self, OrchestratorError> {
+        let mut lines = data_lines(text);
+        let (_, description) = lines
+            .next()
+            .ok_or_else(|| invalid(0, "missing the description line"))?;
+        let (number, count) = lines
+            .next()
+            .ok_or_else(|| invalid(0, "missing the number of notes"))?;
+        let count: usize = first_word(count)
+            .parse()
+            .map_err(|_| invalid(number, "expected the number of notes"))?;
+        if count == 0 {
+            return Err(invalid(number, "a scale needs at least one note"));
+        }
+
+        let mut degrees = Vec::with_capacity(count);
+        for (number, line) in lines.take(count) {
+            let cents = parse_pitch(first_word(line)).ok_or_else(|| {
+                invalid(
+                    number,
+                    "expected a pitch in cents (e.g. 701.955) or a ratio (e.g. 3/2)",
+                )
+            })?;
+            degrees.push(cents);
+        }
+        if degrees.len() < count {
+            return Err(OrchestratorError::InvalidTuning(format!(
+                "the scale declares {} notes but lists {}",
+                count,
+                degrees.len()
+            )));
+        }
+        if degrees[count - 1] <= 0.0 {
+            return Err(OrchestratorError::InvalidTuning(
+                "the last pitch of a scale is its period and must be above the first degree"
+                    .to_string(),
+            ));
+        }
+
+        let description = match description.trim() {
+            "" => "Untitled scale",
+            description => description,
+        };
+        Ok(Scale {
+            description: description.to_string(),
+            degrees,
+        })
+    }
+
+    /// Twelve equal steps per octave
+    pub fn equal_temperament() -> Self {
+        Scale {
+            description: "12-tone equal temperament".to_string(),
+            degrees: (1..=CHROMATIC_NOTES)
+                .map(|step| step as f64 * 100.0)
+                .collect(),
+        }
+    }
+
+    /// Five-limit just intonation built on C
+    pub fn just_intonation() -> Self {
+        Scale::from_ratios(
+            "Just intonation",
+            &[
+                (16, 15),
+                (9, 8),
+                (6, 5),
+                (5, 4),
+                (4, 3),
+                (45, 32),
+                (3, 2),
+                (8, 5),
+                (5, 3),
+                (9, 5),
+                (15, 8),
+                (2, 1),
+            ],
+        )
+    }
+
+    /// Pure fifths stacked from E♭ to G♯
+    pub fn pythagorean() -> Self {
+        Scale::from_ratios(
+            "Pythagorean tuning",
+            &[
+                (256, 243),
+                (9, 8),
+                (32, 27),
+                (81, 64),
+                (4, 3),
+                (729, 512),
+                (3, 2),
+                (128, 81),
+                (27, 16),
+                (16, 9),
+                (243, 128),
+                (2, 1),
+            ],
+        )
+    }
+
+    /// Quarter-comma meantone, fifths narrowed so major thirds are pure, from E♭ to G♯
+    pub fn meantone() -> Self {
+        let fifth = CENTS_PER_OCTAVE * 5_f64.log2() / 4.0;
+        // Position of each note of the octave on the chain of fifths, counted from C
+        let fifths_from_c = [7, 2, -3, 4, -1, 6, 1, 8, 3, -2, 5];
+        let mut degrees: Vec<f64> = fifths_from_c
+            .iter()
+            .map(|&fifths| (fifths as f64 * fifth).rem_euclid(CENTS_PER_OCTAVE))
+            .collect();
+        degrees.push(CENTS_PER_OCTAVE);
+        Scale {
+            description: "Quarter-comma meantone".to_string(),
+            degrees,
+        }
+    }
+
+    fn from_ratios(description: &str, ratios: &[(u32, u32)]) -> Self {
+        Scale {
+            description: description.to_string(),
+            degrees: ratios
+                .iter()
+                .map(|&(numerator, denominator)| ratio_cents(numerator as f64, denominator as f64))
+                .collect(),
+        }
+    }
+
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// Number of notes in one period
+    pub fn notes(&self) -> usize {
+        self.degrees.len()
+    }
+
+    /// Cents of a degree above the first degree, counting whole periods for degrees past the end
+    pub fn cents(&self, degree: i64) -> f64 {
+        let steps = self.degrees.len() as i64;
+        let period = self.degrees[self.degrees.len() - 1];
+        let step = degree.rem_euclid(steps) as usize;
+        let within = if step == 0 {
+            0.0
+        } else {
+            self.degrees[step - 1]
+        };
+        degree.div_euclid(steps) as f64 * period + within
+    }
+}
+
+/// Which scale degree each key plays, read from a Scala `.kbm` file
+/// Keys are numbered `(octave + 1) × notes per octave + id`, which are MIDI note numbers
+/// when an octave has twelve notes.
+#[derive(Debug, Clone)]
+pub struct KeyboardMap {
+    keys: Option<usize>, // keys before the mapping repeats, none when linear
+    first_key: i64,
+    last_key: i64,
+    middle_key: i64, // plays the first scale degree
+    reference_key: i64,
+    reference_frequency: f64,  // in Hz
+    octave_degree: i64,        // degree added each time the mapping repeats
+    mapping: Vec<Option<i64>>, // empty when keys map to consecutive degrees
+}
+
+impl KeyboardMap {
+    /// Maps consecutive keys to consecutive degrees, as Scala does without a `.kbm` file
+    /// The first degree is on id 0 of octave 4 and the reference nine keys above it.
+    pub fn linear(notes: usize, reference_frequency: f64) -> Self {
+        let middle_key = (DEFAULT_MIDDLE_OCTAVE + 1) * notes as i64;
+        KeyboardMap {
+            keys: None,
+            first_key: i64::MIN,
+            last_key: i64::MAX,
+            middle_key,
+            reference_key: middle_key + DEFAULT_REFERENCE_STEPS,
+            reference_frequency,
+            octave_degree: notes as i64,
+            mapping: Vec::new(),
+        }
+    }
+
+    /// Parses the text of a Scala `.kbm` file
+    pub fn parse(text: &str) -> Result<Self, OrchestratorError> {
+        let mut lines = data_lines(text);
+        let mut header = |what: &str| -> Result<(usize, &str), OrchestratorError> {
+            let (number, line) = lines
+                .next()
+                .ok_or_else(|| invalid(0, &format!("missing the {}", what)))?;
+            Ok((number, first_word(line)))
+        };
+        let integer = |(number, word): (usize, &str), what: &str| {
+            word.parse::<i64>()
+                .map_err(|_| invalid(number, &format!("expected the {}", what)))
+        };
+
+        let size = integer(header("map size")?, "map size")?;
+        let first_key = integer(header("first key")?, "first key")?;
+        let last_key = integer(header("last key")?, "last key")?;
+        let middle_key = integer(header("middle key")?, "middle key")?;
+        let reference_key = integer(header("reference key")?, "reference key")?;
+        let (number, word) = header("reference frequency")?;
+        let reference_frequency = word
+            .parse::<f64>()
+            .ok()
+            .filter(|frequency| frequency.is_finite() && *frequency > 0.0)
+            .ok_or_else(|| invalid(number, "expected a reference frequency in Hz above 0"))?;
+        let octave_degree = integer(header("octave degree")?, "octave degree")?;
+
+        if size < 0 {
+            return Err(OrchestratorError::InvalidTuning(format!(
+                "the map size must be 0 or more, got {}",
+                size
+            )));
+        }
+        let size = size as usize;
+        let mut mapping = Vec::with_capacity(size);
+        // Keys missing from the end of the mapping are unmapped
+        for (number, line) in lines.take(size) {
+            let entry = match first_word(line) {
+                UNMAPPED => None,
+                word => Some(
+                    word.parse::<i64>()
+                        .map_err(|_| invalid(number, "expected a scale degree or 'x'"))?,
+                ),
+            };
+            mapping.push(entry);
+        }
+        mapping.resize(size, None);
+
+        let map = KeyboardMap {
+            keys: (size > 0).then_some(size),
+            first_key,
+            last_key,
+            middle_key,
+            reference_key,
+            reference_frequency,
+            octave_degree,
+            mapping,
+        };
+        if map.degree(reference_key).is_none() {
+            return Err(OrchestratorError::InvalidTuning(format!(
+                "the reference key {} isn't mapped to a scale degree",
+                reference_key
+            )));
+        }
+        Ok(map)
+    }
+
+    /// Keys before the mapping repeats, `None` when every key plays the next degree
+    pub fn keys(&self) -> Option<usize> {
+        self.keys
+    }
+
+    pub fn reference_key(&self) -> i64 {
+        self.reference_key
+    }
+
+    pub fn reference_frequency(&self) -> f64 {
+        self.reference_frequency
+    }
+
+    /// Scale degree played by a key, counted from the first degree on the middle key
+    /// Keys outside the mapped range or marked with `x` play nothing.
+    pub fn degree(&self, key: i64) -> Option<i64> {
+        if key < self.first_key || key > self.last_key {
+            return None;
+        }
+        let offset = key - self.middle_key;
+        if self.mapping.is_empty() {
+            return Some(offset);
+        }
+        let size = self.mapping.len() as i64;
+        let entry = self.mapping[offset.rem_euclid(size) as usize]?;
+        Some(offset.div_euclid(size) * self.octave_degree + entry)
+    }
+}
+
+// Numbered lines of a Scala file without comments, numbers are 1-based
+fn data_lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line))
+        .filter(|(_, line)| !line.starts_with(COMMENT))
+}
+
+// Values may be followed by text, which Scala ignores
+fn first_word(line: &str) -> &str {
+    line.split_whitespace().next().unwrap_or("")
+}
+
+// Pitches with a dot are in cents, others are ratios such as `3/2` or `2`
+fn parse_pitch(word: &str) -> Option<f64> {
+    if word.contains('.') {
+        return word.parse::<f64>().ok().filter(|cents| cents.is_finite());
+    }
+    let (numerator, denominator) = word.split_once('/').unwrap_or((word, "1"));
+    let numerator: u64 = numerator.parse().ok()?;
+    let denominator: u64 = denominator.parse().ok()?;
+    if numerator == 0 || denominator == 0 {
+        return None;
+    }
+    Some(ratio_cents(numerator as f64, denominator as f64))
+}
+
+fn ratio_cents(numerator: f64, denominator: f64) -> f64 {
+    CENTS_PER_OCTAVE * (numerator / denominator).log2()
+}
+
+fn invalid(line: usize, message: &str) -> OrchestratorError {
+    if line == 0 {
+        OrchestratorError::InvalidTuning(message.to_string())
+    } else {
+        OrchestratorError::InvalidTuning(format!("line {}: {}", line, message))
+    }
+}
@@ -0,0 +1,435 @@
+use crate::sampler::{VolumeEnvelope, Zone, midi_frequency};
+use anyhow::{Context, Result, bail};
+use std::collections::HashMap;
+use std::fs;
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Highest preset number of a bank
+pub const MAX_PRESET: u16 = 127;
+/// Highest bank number, General MIDI files keep their drum kits in it
+pub const MAX_BANK: u16 = 128;
+
+// Sizes of the records of the pdta chunks
+const PRESET_HEADER: usize = 38;
+const INSTRUMENT_HEADER: usize = 22;
+const BAG: usize = 4;
+const GENERATOR: usize = 4;
+const SAMPLE_HEADER: usize = 46;
+
+// Generators read, numbered as in the SoundFont 2 specification
+const START_OFFSET: u16 = 0;
+const END_OFFSET: u16 = 1;
+const LOOP_START_OFFSET: u16 = 2;
+const LOOP_END_OFFSET: u16 = 3;
+const START_COARSE_OFFSET: u16 = 4;
+const END_COARSE_OFFSET: u16 = 12;
+const DELAY_VOLUME: u16 = 33;
+const ATTACK_VOLUME: u16 = 34;
+const HOLD_VOLUME: u16 = 35;
+const DECAY_VOLUME: u16 = 36;
+const SUSTAIN_VOLUME: u16 = 37;
+const RELEASE_VOLUME: u16 = 38;
+const INSTRUMENT: u16 = 41;
+const KEY_RANGE: u16 = 43;
+const VELOCITY_RANGE: u16 = 44;
+const LOOP_START_COARSE_OFFSET: u16 = 45;
+const ATTENUATION: u16 = 48;
+const LOOP_END_COARSE_OFFSET: u16 = 50;
+const COARSE_TUNE: u16 = 51;
+const FINE_TUNE: u16 = 52;
+const SAMPLE_ID: u16 = 53;
+const SAMPLE_MODES: u16 = 54;
+const SCALE_TUNING: u16 = 56;
+const ROOT_KEY: u16 = 58;
+
+// Generators a preset zone adds to those of its instrument zones
+const ADDITIVE: [u16; 10] = [
+    DELAY_VOLUME,
+    ATTACK_VOLUME,
+    HOLD_VOLUME,
+    DECAY_VOLUME,
+    SUSTAIN_VOLUME,
+    RELEASE_VOLUME,
+    ATTENUATION,
+    COARSE_TUNE,
+    FINE_TUNE,
+    SCALE_TUNING,
+];
+
+// Envelope times are in timecents, this one is about a millisecond
+const DEFAULT_TIME: i16 = -12000;
+// Attenuations are in centibels, and at this one a sound is silent
+const SILENT: f64 = 1440.0;
+const SEMITONE_CENTS: f64 = 100.0;
+// Root key of samples that don't say, middle C
+const DEFAULT_ROOT_KEY: u8 = 60;
+const UNKNOWN_ROOT_KEY: u8 = 255;
+
+// Bits of a sample's type
+const LINKED_SIDE: u16 = 0x2 | 0x4; // right or left sample of a stereo pair
+const ROM_SAMPLE: u16 = 0x8000; // stored in a sound card's memory rather than the file
+
+// Sample modes, and the mask keeping them
+const LOOP: i16 = 1;
+const LOOP_UNTIL_RELEASE: i16 = 3;
+const MODES: i16 = 3;
+
+// Generators of a zone by number, amounts kept raw as their meaning depends on the generator
+type Generators = HashMap<u16, u16>;
+
+/// A preset of a SoundFont, read into zones a layered sampler plays
+pub struct Preset {
+    pub name: String,
+    pub zones: Vec<Zone>,
+}
+
+/// Reads a preset from a SoundFont 2 (`.sf2`) file
+/// Key and velocity ranges, tuning, attenuation, the volume envelope and loops are
+/// read, modulators, filters, LFOs and the modulation envelope are ignored.
+pub fn read(path: &Path, bank: u16, preset: u16) -> Result<Preset> {
+    let bytes = fs::read(path).context(format!("Failed to read SoundFont: {}", path.display()))?;
+    parse(&bytes, bank, preset).context(format!("Failed to parse SoundFont: {}", path.display()))
+}
+
+fn parse(bytes: &[u8], bank: u16, number: u16) -> Result<Preset> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"sfbk" {
+        bail!("Not a SoundFont 2 file");
+    }
+
+    let mut samples = None;
+    let mut low_bytes = None;
+    let mut records = HashMap::new();
+    for (id, body) in chunks(&bytes[12..]) {
+        if id != b"LIST" || body.len() < 4 {
+            continue;
+        }
+        for (id, chunk) in chunks(&body[4..]) {
+            match (&body[0..4], id) {
+                (b"sdta", b"smpl") => samples = Some(chunk),
+                (b"sdta", b"sm24") => low_bytes = Some(chunk),
+                (b"pdta", _) => {
+                    records.insert(id, chunk);
+                }
+                _ => {}
+            }
+        }
+    }
+    let Some(samples) = samples else {
+        bail!("Missing smpl chunk");
+    };
+    let record = |id: &[u8; 4], size: usize| -> Result<Vec<&[u8]>> {
+        let Some(chunk) = records.get(id) else {
+            bail!("Missing {} chunk", String::from_utf8_lossy(id));
+        };
+        Ok(chunk.chunks_exact(size).collect())
+    };
+    let presets = record(b"phdr", PRESET_HEADER)?;
+    let preset_bags = record(b"pbag", BAG)?;
+    let preset_generators = record(b"pgen", GENERATOR)?;
+    let instruments = record(b"inst", INSTRUMENT_HEADER)?;
+    let instrument_bags = record(b"ibag", BAG)?;
+    let instrument_generators = record(b"igen", GENERATOR)?;
+    let headers = record(b"shdr", SAMPLE_HEADER)?;
+
+    // The 24-bit extension holds a low byte for each 16-bit sample
+    let samples: Vec<f64> = samples
+        .chunks_exact(2)
+        .enumerate()
+        .map(|(index, pair)| {
+            let high = i16::from_le_bytes([pair[0], pair[1]]);
+            match low_bytes.and_then(|bytes| bytes.get(index)) {
+                Some(&low) => (((high as i32) << 8) | low as i32) as f64 / 8388608.0,
+                None => high as f64 / 32768.0,
+            }
+        })
+        .collect();
+
+    // Every list ends with a terminal record, which only marks where the last entry ends
+    let Some(position) = presets[..presets.len().saturating_sub(1)]
+        .iter()
+        .position(|header| word(header, 20) == number && word(header, 22) == bank)
+    else {
+        bail!(
+            "No preset {} in bank {}, the file has {} presets",
+            number,
+            bank,
+            presets.len().saturating_sub(1)
+        );
+    };
+    let name = text(&presets[position][0..20]);
+    let preset_zones = zones(
+        &preset_bags,
+        &preset_generators,
+        word(presets[position], 24)..word(presets[position + 1], 24),
+        INSTRUMENT,
+    )?;
+
+    let mut shared = HashMap::new();
+    let mut result = Vec::new();
+    for (preset_global, preset_zone) in &preset_zones {
+        let Some(&instrument) = preset_zone.get(&INSTRUMENT) else {
+            continue;
+        };
+        let instrument = instrument as usize;
+        if instrument + 1 >= instruments.len() {
+            bail!(
+                "Preset '{}' plays a missing instrument {}",
+                name,
+                instrument
+            );
+        }
+        let instrument_zones = zones(
+            &instrument_bags,
+            &instrument_generators,
+            word(instruments[instrument], 20)..word(instruments[instrument + 1], 20),
+            SAMPLE_ID,
+        )?;
+        for (instrument_global, instrument_zone) in &instrument_zones {
+            let Some(&sample) = instrument_zone.get(&SAMPLE_ID) else {
+                continue;
+            };
+            let Some(generators) = combine(
+                &merge(instrument_global, instrument_zone),
+                &merge(preset_global, preset_zone),
+            ) else {
+                continue;
+            };
+            let Some(header) = headers.get(sample as usize) else {
+                bail!("Instrument zone plays a missing sample {}", sample);
+            };
+            if let Some(zone) = zone(&generators, header, &samples, &mut shared)? {
+                result.push(zone);
+            }
+        }
+    }
+    if result.is_empty() {
+        bail!("Preset '{}' plays no samples", name);
+    }
+    Ok(Preset {
+        name,
+        zones: result,
+    })
+}
+
+// Sub-chunks of a RIFF chunk's body, each padded to an even size
+fn chunks(bytes: &[u8]) -> impl Iterator<Item = (&[u8; 4], &[u8])> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        if offset + 8 > bytes.len() {
+            return None;
+        }
+        let id = bytes[offset..offset + 4].try_into().ok()?;
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().ok()?) as usize;
+        let body = &bytes[offset + 8..(offset + 8 + size).min(bytes.len())];
+        offset += 8 + size + size % 2;
+        Some((id, body))
+    })
+}
+
+fn word(record: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([record[at], record[at + 1]])
+}
+
+fn long(record: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([record[at], record[at + 1], record[at + 2], record[at + 3]])
+}
+
+// Name fields are padded with zeros
+fn text(bytes: &[u8]) -> String {
+    let end = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+// Zones of a preset or an instrument, each paired with the global zone
+// The first zone is global when it doesn't end in the generator naming what it plays.
+fn zones(
+    bags: &[&[u8]],
+    generators: &[&[u8]],
+    range: Range<u16>,
+    last: u16,
+) -> Result<Vec<(Generators, Generators)>> {
+    let mut zones = Vec::new();
+    for bag in range.start as usize..range.end as usize {
+        let (Some(start), Some(end)) = (bags.get(bag), bags.get(bag + 1)) else {
+            bail!("Zone {} points past the end of its bag list", bag);
+        };
+        let zone: Generators = generators
+            .get(word(start, 0) as usize..word(end, 0) as usize)
+            .unwrap_or_default()
+            .iter()
+            .map(|generator| (word(generator, 0), word(generator, 2)))
+            .collect();
+        zones.push(zone);
+    }
+    let global = match zones.first() {
+        Some(first) if !first.contains_key(&last) => zones.remove(0),
+        _ => Generators::new(),
+    };
+    Ok(zones
+        .into_iter()
+        .map(|zone| (global.clone(), zone))
+        .collect())
+}
+
+// A zone's generators over those of its global zone
+fn merge(global: &Generators, zone: &Generators) -> Generators {
+    let mut merged = global.clone();
+    merged.extend(zone);
+    merged
+}
+
+// Generators of an instrument zone as a preset zone plays it, `None` when their key or
+// velocity ranges don't meet
+fn combine(instrument: &Generators, preset: &Generators) -> Option<Generators> {
+    let mut combined = instrument.clone();
+    for range in [KEY_RANGE, VELOCITY_RANGE] {
+        let (low, high) = intersect(limits(instrument.get(&range)), limits(preset.get(&range)))?;
+        combined.insert(range, u16::from_le_bytes([low, high]));
+    }
+    for generator in ADDITIVE {
+        if let Some(&amount) = preset.get(&generator) {
+            let base = instrument
+                .get(&generator)
+                .map_or(default(generator), |&amount| amount as i16);
+            combined.insert(generator, base.saturating_add(amount as i16) as u16);
+        }
+    }
+    Some(combined)
+}
+
+// Lowest and highest values of a range generator, which packs them into its two bytes
+fn limits(amount: Option<&u16>) -> (u8, u8) {
+    amount.map_or((0, 127), |amount| {
+        let [low, high] = amount.to_le_bytes();
+        (low, high)
+    })
+}
+
+fn intersect(a: (u8, u8), b: (u8, u8)) -> Option<(u8, u8)> {
+    let (low, high) = (a.0.max(b.0), a.1.min(b.1));
+    (low <= high).then_some((low, high))
+}
+
+// Value of a generator a zone doesn't set
+fn default(generator: u16) -> i16 {
+    match generator {
+        DELAY_VOLUME | ATTACK_VOLUME | HOLD_VOLUME | DECAY_VOLUME | RELEASE_VOLUME => DEFAULT_TIME,
+        SCALE_TUNING => SEMITONE_CENTS as i16,
+        ROOT_KEY => -1,
+        _ => 0,
+    }
+}
+
+// Builds a zone from its generators and sample header, `None` for samples not in the file
+fn zone(
+    generators: &Generators,
+    header: &[u8],
+    samples: &[f64],
+    shared: &mut HashMap<(usize, usize), Arc<Vec<f64>>>,
+) -> Result<Option<Zone>> {
+    let amount = |generator: u16| {
+        generators
+            .get(&generator)
+            .map_or(default(generator), |&amount| amount as i16)
+    };
+    // Address offsets are split into a fine part and a coarse part of 32768 samples
+    let offset = |fine: u16, coarse: u16| amount(fine) as i64 + amount(coarse) as i64 * 32768;
+    let sample_type = word(header, 44);
+    if sample_type & ROM_SAMPLE != 0 {
+        return Ok(None);
+    }
+    let start = long(header, 20) as i64 + offset(START_OFFSET, START_COARSE_OFFSET);
+    let end = long(header, 24) as i64 + offset(END_OFFSET, END_COARSE_OFFSET);
+    let loop_start = long(header, 28) as i64 + offset(LOOP_START_OFFSET, LOOP_START_COARSE_OFFSET);
+    let loop_end = long(header, 32) as i64 + offset(LOOP_END_OFFSET, LOOP_END_COARSE_OFFSET);
+    if start < 0 || start >= end || end as usize > samples.len() {
+        bail!(
+            "Sample '{}' lies outside the sample data ({}..{} of {} samples)",
+            text(&header[0..20]),
+            start,
+            end,
+            samples.len()
+        );
+    }
+    let sample_rate = long(header, 36);
+    if sample_rate == 0 {
+        bail!(
+            "Sample '{}' has a sample rate of 0 Hz",
+            text(&header[0..20])
+        );
+    }
+    let (start, end) = (start as usize, end as usize);
+    let data = shared
+        .entry((start, end))
+        .or_insert_with(|| Arc::new(samples[start..end].to_vec()))
+        .clone();
+
+    let mode = amount(SAMPLE_MODES) & MODES;
+    // Broken loops are dropped, as many files have them on samples that don't loop
+    let sample_loop = (mode == LOOP || mode == LOOP_UNTIL_RELEASE)
+        .then(|| (loop_start - start as i64)..(loop_end - start as i64))
+        .filter(|range| {
+            range.start >= 0 && range.start < range.end && range.end as usize <= data.len()
+        })
+        .map(|range| range.start as usize..range.end as usize);
+
+    let root_key = match amount(ROOT_KEY) {
+        key @ 0..=127 => key as u8,
+        _ => match header[40] {
+            UNKNOWN_ROOT_KEY => DEFAULT_ROOT_KEY,
+            key => key.min(127),
+        },
+    };
+    let correction = header[41] as i8 as f64;
+    let cents = amount(COARSE_TUNE) as f64 * SEMITONE_CENTS + amount(FINE_TUNE) as f64 + correction;
+    let tracking = amount(SCALE_TUNING) as f64 / SEMITONE_CENTS;
+
+    let (low_key, high_key) = limits(generators.get(&KEY_RANGE));
+    let (low_velocity, high_velocity) = limits(generators.get(&VELOCITY_RANGE));
+    let mut gain = decibels(amount(ATTENUATION) as f64);
+    // Both sides of a stereo pair play, each at half level in mono
+    if sample_type & LINKED_SIDE != 0 {
+        gain *= 0.5;
+    }
+
+    let mut zone = Zone::new(
+        data,
+        sample_rate,
+        Some(midi_frequency(root_key as f64)),
+        midi_frequency(low_key as f64)..midi_frequency(high_key as f64),
+        sample_loop,
+    )?
+    .with_velocities(RangeInclusive::new(low_velocity, high_velocity))
+    .with_tuning(tracking, cents)
+    .with_gain(gain)
+    .with_envelope(VolumeEnvelope {
+        delay: seconds(amount(DELAY_VOLUME)),
+        attack: seconds(amount(ATTACK_VOLUME)),
+        hold: seconds(amount(HOLD_VOLUME)),
+        decay: seconds(amount(DECAY_VOLUME)),
+        sustain: decibels(amount(SUSTAIN_VOLUME) as f64),
+        release: seconds(amount(RELEASE_VOLUME)),
+    });
+    if mode == LOOP_UNTIL_RELEASE {
+        zone = zone.with_release_from_loop();
+    }
+    Ok(Some(zone))
+}
+
+fn seconds(timecents: i16) -> f64 {
+    2_f64.powf(timecents as f64 / 1200.0)
+}
+
+// Level of an attenuation in centibels
+fn decibels(centibels: f64) -> f64 {
+    if centibels >= SILENT {
+        0.0
+    } else {
+        10_f64.powf(-centibels.max(0.0) / 200.0)
+    }
+}
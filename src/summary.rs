@@ -0,0 +1,295 @@
+use crate::analysis::{Voice, sweep, voices};
+use crate::cli::AnyOrchestrator;
+use crate::oscillator::{self, CycleStats, measure_cycle};
+use crate::timeline_orchestrator::CONDENSE_CONSTANT;
+use crate::voice::SynthVoice;
+use serde::Serialize;
+use std::f64::consts::TAU;
+
+/// Overview of a parsed score, computed without rendering it
+#[derive(Debug, Serialize)]
+pub struct Summary {
+    pub tempo: u8, // beats per minute
+    pub tuning: String,
+    pub reference_note: String, // key tuned to the reference pitch, e.g. `A4`
+    pub reference_pitch: f64,   // in Hz
+    pub duration_beats: f64,
+    pub duration_seconds: f64,
+    pub tracks: Vec<TrackSummary>,
+    pub waveform: Waveform,
+    pub voices: Vec<VoiceSummary>, // sorted by name
+    pub lowest_pitch: Option<Pitch>,
+    pub highest_pitch: Option<Pitch>,
+    pub polyphony_peak: usize,
+    pub peak_level: f64, // estimated, 1.0 is full scale
+    pub peak_dbfs: f64,
+}
+
+/// Note count of one track, the top-level notes are listed as `notes`
+#[derive(Debug, Serialize)]
+pub struct TrackSummary {
+    pub name: String,
+    pub notes: usize,
+}
+
+/// Level of one cycle of the waveform pitched notes play, at full amplitude
+#[derive(Debug, Serialize)]
+pub struct Waveform {
+    pub name: String,
+    pub dc: f64, // mean value, 0.0 when the wave is centred
+    pub rms: f64,
+}
+
+/// A named voice from the top-level `voices` object
+#[derive(Debug, Serialize)]
+pub struct VoiceSummary {
+    pub name: String,
+    pub description: String, // e.g. `FM, 4 operators (stack)`
+}
+
+#[derive(Debug, Serialize)]
+pub struct Pitch {
+    pub name: String,
+    pub frequency: f64,
+}
+
+impl Summary {
+    /// Rows for printing the summary as a table
+    pub fn rows(&self) -> Vec<(String, String)> {
+        let mut rows = vec![
+            ("Tempo".to_string(), format!("{} bpm", self.tempo)),
+            ("Tuning".to_string(), self.tuning.clone()),
+            (
+                "Reference pitch".to_string(),
+                format!("{} = {} Hz", self.reference_note, self.reference_pitch),
+            ),
+            (
+                "Duration".to_string(),
+                format!(
+                    "{:.2} beats ({:.2}s)",
+                    self.duration_beats, self.duration_seconds
+                ),
+            ),
+        ];
+        for track in &self.tracks {
+            rows.push((track.name.clone(), format!("{} notes", track.notes)));
+        }
+        let pitch_range = match (&self.lowest_pitch, &self.highest_pitch) {
+            (Some(low), Some(high)) => format!(
+                "{} ({:.2} Hz) to {} ({:.2} Hz)",
+                low.name, low.frequency, high.name, high.frequency
+            ),
+            _ => "no pitched notes".to_string(),
+        };
+        rows.push((
+            "Waveform".to_string(),
+            format!(
+                "{}: DC {:+.3}, RMS {:.3}",
+                self.waveform.name, self.waveform.dc, self.waveform.rms
+            ),
+        ));
+        for voice in &self.voices {
+            rows.push((format!("voices.{}", voice.name), voice.description.clone()));
+        }
+        rows.push(("Pitch range".to_string(), pitch_range));
+        rows.push((
+            "Polyphony peak".to_string(),
+            format!("{} voices", self.polyphony_peak),
+        ));
+        rows.push((
+            "Peak level".to_string(),
+            format!(
+                "{:.3} ({:.1} dBFS, estimated)",
+                self.peak_level, self.peak_dbfs
+            ),
+        ));
+        rows
+    }
+}
+
+/// Summarises a parsed score
+pub fn summarize(orchestrator: &AnyOrchestrator) -> Summary {
+    let voices = voices(orchestrator);
+    let mix = sweep(&voices);
+    let polyphony_peak = mix.iter().map(|point| point.polyphony).max().unwrap_or(0);
+    let loudest = mix.iter().map(|point| point.amplitude).fold(0.0, f64::max);
+
+    let (tempo, tuning, duration_seconds, tracks, peak_level) = match orchestrator {
+        AnyOrchestrator::Regular(regular) => {
+            let seconds_per_beat = 60.0 / regular.bpm() as f64;
+            let beats: f64 = regular.notes().iter().map(|note| note.beats()).sum();
+            let tracks = vec![TrackSummary {
+                name: "notes".to_string(),
+                notes: regular.note_count(),
+            }];
+            // Notes play one at a time and are clamped, not soft clipped
+            (
+                regular.bpm(),
+                regular.tuning(),
+                beats * seconds_per_beat,
+                tracks,
+                loudest.min(1.0),
+            )
+        }
+        AnyOrchestrator::Timeline(timeline) => {
+            let mut tracks = vec![TrackSummary {
+                name: "notes".to_string(),
+                notes: timeline.notes().len(),
+            }];
+            for (index, track) in timeline.tracks().iter().enumerate() {
+                tracks.push(TrackSummary {
+                    name: format!("tracks[{}] ({})", index, track.kind()),
+                    notes: track.note_count(),
+                });
+            }
+            // Assumes every overlapping voice peaks at once, so this is an upper bound
+            (
+                timeline.bpm(),
+                timeline.tuning(),
+                timeline.duration_in_seconds(),
+                tracks,
+                (loudest * CONDENSE_CONSTANT).tanh(),
+            )
+        }
+    };
+    let seconds_per_beat = 60.0 / tempo as f64;
+
+    Summary {
+        tempo,
+        tuning: tuning.name().to_string(),
+        reference_note: tuning.reference_note(),
+        reference_pitch: tuning.reference_pitch(),
+        duration_beats: duration_seconds / seconds_per_beat,
+        duration_seconds,
+        tracks,
+        waveform: waveform(orchestrator),
+        voices: voice_summaries(orchestrator),
+        lowest_pitch: pitch(&voices, |a, b| b < a),
+        highest_pitch: pitch(&voices, |a, b| b > a),
+        polyphony_peak,
+        peak_level,
+        peak_dbfs: 20.0 * peak_level.log10(),
+    }
+}
+
+// The pitched voice whose frequency wins against every other one
+fn pitch(voices: &[Voice], wins: impl Fn(f64, f64) -> bool) -> Option<Pitch> {
+    voices
+        .iter()
+        .filter_map(|voice| voice.frequency.map(|frequency| (voice, frequency)))
+        .reduce(|best, next| if wins(best.1, next.1) { next } else { best })
+        .map(|(voice, frequency)| Pitch {
+            name: voice.label.clone(),
+            frequency,
+        })
+}
+
+// The waveform pitched notes play, measured at full amplitude
+fn waveform(orchestrator: &AnyOrchestrator) -> Waveform {
+    let (name, stats) = match orchestrator.waveform() {
+        oscillator::Waveform::Sine => (
+            "Sine".to_string(),
+            measure_cycle(|phase| (TAU * phase).sin()),
+        ),
+        oscillator::Waveform::Bezier(wave) => {
+            let segments = wave.control_points().segments();
+            let mut name = format!(
+                "Bezier, {} segment{}",
+                segments,
+                if segments == 1 { "" } else { "s" }
+            );
+            let shaping = wave.shaping();
+            let cleanup: Vec<&str> = [
+                (shaping.close_loop, "loop closed"),
+                (shaping.dc_block, "DC blocked"),
+            ]
+            .into_iter()
+            .filter_map(|(on, label)| on.then_some(label))
+            .collect();
+            if !cleanup.is_empty() {
+                name.push_str(&format!(" ({})", cleanup.join(", ")));
+            }
+            (name, wave.stats())
+        }
+        oscillator::Waveform::Wavetable(wave) => {
+            // Measured at the starting position, before any modulation moves it
+            let frames = wave.frames();
+            let position = wave.morph().position;
+            let name = format!(
+                "Wavetable, {} frame{} at position {:.2}",
+                frames.count(),
+                if frames.count() == 1 { "" } else { "s" },
+                position
+            );
+            (
+                name,
+                measure_cycle(|phase| frames.value(0, position, phase)),
+            )
+        }
+        oscillator::Waveform::Additive(partials) => {
+            // Partials at other ratios don't repeat with the note's cycle, so their
+            // powers add up instead of being measured, as they would at the note's start
+            let total: f64 = partials.iter().map(|partial| partial.amplitude).sum();
+            let power: f64 = partials
+                .iter()
+                .map(|partial| partial.amplitude * partial.amplitude / 2.0)
+                .sum();
+            let name = format!(
+                "Additive, {} partial{}",
+                partials.len(),
+                if partials.len() == 1 { "" } else { "s" }
+            );
+            let stats = CycleStats {
+                dc: 0.0,
+                rms: power.sqrt() / total.max(1.0),
+                peak: total / total.max(1.0),
+            };
+            (name, stats)
+        }
+    };
+    Waveform {
+        name,
+        dc: stats.dc,
+        rms: stats.rms,
+    }
+}
+
+// The named voices of a timeline score, empty for regular scores
+fn voice_summaries(orchestrator: &AnyOrchestrator) -> Vec<VoiceSummary> {
+    let AnyOrchestrator::Timeline(timeline) = orchestrator else {
+        return Vec::new();
+    };
+    let mut voices: Vec<VoiceSummary> = timeline
+        .voices()
+        .iter()
+        .map(|(name, voice)| VoiceSummary {
+            name: name.clone(),
+            description: match voice {
+                SynthVoice::Fm(fm) => format!(
+                    "FM, {} operators ({})",
+                    fm.operators.len(),
+                    fm.algorithm.name()
+                ),
+                SynthVoice::Pluck(pluck) => format!(
+                    "Plucked string, rings {:.2}s, brightness {:.2}",
+                    pluck.ring(),
+                    pluck.brightness
+                ),
+                SynthVoice::Sampler(sampler) => format!(
+                    "Sampler, {} zone{} ({} interpolation)",
+                    sampler.zones().len(),
+                    if sampler.zones().len() == 1 { "" } else { "s" },
+                    sampler.interpolation().name()
+                ),
+                SynthVoice::SoundFont(sampler) => format!(
+                    "SoundFont, {} zone{} ({} interpolation)",
+                    sampler.zones().len(),
+                    if sampler.zones().len() == 1 { "" } else { "s" },
+                    sampler.interpolation().name()
+                ),
+            },
+        })
+        .collect();
+    voices.sort_by(|a, b| a.name.cmp(&b.name));
+    voices
+}
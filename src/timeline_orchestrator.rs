@@ -1,239 +1,582 @@
 use crate::adsr::ADSREnvelope;
+use crate::arrangement::SectionMarker;
+use crate::bezier::{BezierWave, ControlPoints, Shaping};
 use crate::errors::OrchestratorError;
-use crate::oscillator::{BezierOscillator, SinOscillator};
-use crate::validation::{validate_bpm, validate_control_points, validate_timeline_notes};
+use crate::oscillator::{
+    AdditiveOscillator, BezierOscillator, Oscillator, Partial, SinOscillator, Waveform,
+    WavetableOscillator,
+};
+use crate::percussion::{PercussionNote, PercussionTrack};
+use crate::progress::{Progress, ProgressTracker};
+use crate::tuning::{Pitch, Tuning};
+use crate::validation::{
+    validate_adsr, validate_bpm, validate_control_points, validate_morph, validate_partials,
+    validate_pitch, validate_song_length, validate_timeline_notes, validate_tracks, validate_voice,
+};
+use crate::voice::{InstrumentTrack, SynthVoice};
+use crate::wavetable::WavetableWave;
 use serde::Deserialize;
+use std::collections::HashMap;
+use std::ops::Range;
 
 // For safe mixing we will condense the amplitude
-const CONDENSE_CONSTANT: f64 = 0.9;
+pub const CONDENSE_CONSTANT: f64 = 0.9;
 const PCM_BIT_RANGE: f64 = 32767.0; // 2^15 - 1
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Clone)]
 pub struct TimelineNote {
-    id: u8,
-    octave: u8,
+    #[serde(flatten)]
+    pitch: Pitch,
     start_time: f64,
     duration: f64,
     amplitude: f64,
+    voice: Option<String>, // name of a voice from `voices`, the timeline's waveform when unset
 }
 
 impl TimelineNote {
-    // Public getters for validation
-    pub fn id(&self) -> u8 {
-        self.id
+    pub fn new(pitch: Pitch, start_time: f64, duration: f64, amplitude: f64) -> Self {
+        Self {
+            pitch,
+            start_time,
+            duration,
+            amplitude,
+            voice: None,
+        }
     }
 
-    pub fn octave(&self) -> u8 {
-        self.octave
+    // Public getters for validation
+    pub fn pitch(&self) -> &Pitch {
+        &self.pitch
     }
 
     pub fn amplitude(&self) -> f64 {
         self.amplitude
     }
 
-    pub fn frequency(&self) -> Result<f64, OrchestratorError> {
-        if self.id > 11 {
-            return Err(OrchestratorError::InvalidNoteId(self.id));
+    pub fn start_time(&self) -> f64 {
+        self.start_time
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    pub fn voice(&self) -> Option<&str> {
+        self.voice.as_deref()
+    }
+
+    pub fn name(&self, tuning: &Tuning) -> String {
+        self.pitch.name(tuning)
+    }
+
+    // Copy of the note moved later on the timeline by the given number of beats
+    pub fn shifted(&self, beats: f64) -> Self {
+        Self {
+            start_time: self.start_time + beats,
+            ..self.clone()
         }
-        let multiplier =
-            (2_f64).powf(((self.id as f64 - 9.0) + 12.0 * (self.octave as f64 - 4.0)) / 12.0);
-        let frequency = 440.0 * multiplier;
-        Ok(frequency)
+    }
+
+    pub fn frequency(&self, tuning: &Tuning) -> Result<f64, OrchestratorError> {
+        tuning.frequency(&self.pitch)
     }
 }
 
-pub enum TimelineOrchestrator {
-    Sine(TimelineSineOrchestrator),
-    Bezier(TimelineBezierOrchestrator),
+/// Extra tracks mixed onto the timeline next to the top-level `notes`
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Track {
+    Percussion(PercussionTrack),
+    Instrument(InstrumentTrack),
 }
 
-impl TimelineOrchestrator {
-    pub fn pcm_samples(&self, sample_rate: u32) -> Result<Vec<i16>, OrchestratorError> {
+impl Track {
+    // Name of the track type as written in the input
+    pub fn kind(&self) -> &'static str {
         match self {
-            TimelineOrchestrator::Sine(sine) => sine.pcm_samples(sample_rate),
-            TimelineOrchestrator::Bezier(bezier) => bezier.pcm_samples(sample_rate),
+            Track::Percussion(_) => "percussion",
+            Track::Instrument(_) => "instrument",
         }
     }
 
-    pub fn new(
-        bpm: u8,
-        notes: Vec<TimelineNote>,
-        control_points: Option<Vec<f64>>,
-        adsr: Option<(f64, f64, f64, f64)>,
-    ) -> Result<Self, OrchestratorError> {
-        // Validate inputs
-        validate_bpm(bpm)?;
-        validate_timeline_notes(&notes)?;
+    pub fn note_count(&self) -> usize {
+        match self {
+            Track::Percussion(track) => track.notes().len(),
+            Track::Instrument(track) => track.notes().len(),
+        }
+    }
 
-        // Extract ADSR values, defaulting sustain to 1.0, others to 0.0 if not provided
-        let (attack, decay, sustain, release) = adsr.unwrap_or((0.0, 0.0, 1.0, 0.0));
+    // Whether the track has no notes, hits or step patterns to play
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Track::Percussion(track) => track.notes().is_empty() && track.patterns().is_empty(),
+            Track::Instrument(track) => track.notes().is_empty() && track.patterns().is_empty(),
+        }
+    }
 
-        if let Some(ref points) = control_points {
-            validate_control_points(points)?;
-            Ok(TimelineOrchestrator::Bezier(TimelineBezierOrchestrator {
-                bpm,
-                notes,
-                control_points: points.clone(),
-                attack,
-                decay,
-                sustain,
-                release,
-            }))
-        } else {
-            Ok(TimelineOrchestrator::Sine(TimelineSineOrchestrator {
-                bpm,
-                notes,
-                attack,
-                decay,
-                sustain,
-                release,
-            }))
-        }
-    }
-
-    pub fn is_bezier(&self) -> bool {
-        matches!(self, TimelineOrchestrator::Bezier(_))
+    // Drum hits of the track, empty for tracks without any
+    pub fn percussion_notes(&self) -> &[PercussionNote] {
+        match self {
+            Track::Percussion(track) => track.notes(),
+            Track::Instrument(_) => &[],
+        }
     }
 
-    pub fn note_count(&self) -> usize {
+    // Pitched notes of the track, empty for tracks without any
+    pub fn pitched_notes(&self) -> &[TimelineNote] {
+        match self {
+            Track::Percussion(_) => &[],
+            Track::Instrument(track) => track.notes(),
+        }
+    }
+
+    // Voice the track's notes play unless they name their own
+    pub fn voice(&self) -> Option<&str> {
+        match self {
+            Track::Percussion(_) => None,
+            Track::Instrument(track) => track.voice(),
+        }
+    }
+
+    // Turns step patterns into notes, done once at parse time
+    pub fn expand_patterns(&mut self) -> Result<(), OrchestratorError> {
+        match self {
+            Track::Percussion(track) => track.expand_patterns(),
+            Track::Instrument(track) => track.expand_patterns(),
+        }
+    }
+
+    // Copy of the track moved later on the timeline by the given number of beats
+    pub fn shifted(&self, beats: f64) -> Self {
+        match self {
+            Track::Percussion(track) => Track::Percussion(track.shifted(beats)),
+            Track::Instrument(track) => Track::Instrument(track.shifted(beats)),
+        }
+    }
+
+    // Last beat at which a note of the track is scheduled to end
+    pub fn end_in_beats(&self, seconds_per_beat: f64) -> f64 {
         match self {
-            TimelineOrchestrator::Sine(sine) => sine.notes.len(),
-            TimelineOrchestrator::Bezier(bezier) => bezier.notes.len(),
+            Track::Percussion(track) => track.end_in_beats(seconds_per_beat),
+            Track::Instrument(track) => track.end_in_beats(),
+        }
+    }
+
+    // End of the grids of the step patterns not expanded yet, in beats
+    pub fn patterns_end_in_beats(&self) -> f64 {
+        match self {
+            Track::Percussion(track) => track.patterns_end_in_beats(),
+            Track::Instrument(track) => track.patterns_end_in_beats(),
+        }
+    }
+
+    // Pitched notes are rendered with the timeline's own notes, so only drums count here
+    fn end_in_seconds(&self, seconds_per_beat: f64) -> f64 {
+        match self {
+            Track::Percussion(track) => track.end_in_seconds(seconds_per_beat),
+            Track::Instrument(_) => 0.0,
+        }
+    }
+
+    fn samples_to_mix(&self, seconds_per_beat: f64, sample_rate: u32) -> usize {
+        match self {
+            Track::Percussion(track) => track.samples_to_mix(seconds_per_beat, sample_rate),
+            Track::Instrument(_) => 0,
+        }
+    }
+
+    fn mix_into(
+        &self,
+        timeline: &mut [f64],
+        seconds_per_beat: f64,
+        sample_rate: u32,
+        progress: &mut ProgressTracker,
+    ) {
+        match self {
+            Track::Percussion(track) => track.mix_into(
+                timeline,
+                seconds_per_beat,
+                sample_rate,
+                CONDENSE_CONSTANT,
+                progress,
+            ),
+            Track::Instrument(_) => {}
         }
     }
 }
 
-pub struct TimelineSineOrchestrator {
+pub struct TimelineOrchestrator {
     bpm: u8, //beats per min
     notes: Vec<TimelineNote>,
+    tracks: Vec<Track>,
+    sections: Vec<SectionMarker>, // only used for reporting
+    length: Option<f64>,          // declared song length in beats
+    waveform: Waveform,
+    voices: HashMap<String, SynthVoice>,
+    tuning: Tuning,
     attack: f64,
     decay: f64,
     sustain: f64,
     release: f64,
 }
 
-impl TimelineSineOrchestrator {
-    pub fn pcm_samples(&self, sample_rate: u32) -> Result<Vec<i16>, OrchestratorError> {
-        let seconds_per_beat = 60.0 / self.bpm as f64;
+impl TimelineOrchestrator {
+    pub fn new(
+        bpm: u8,
+        notes: Vec<TimelineNote>,
+        tracks: Vec<Track>,
+        control_points: Option<ControlPoints>,
+        adsr: Option<(f64, f64, f64, f64)>,
+    ) -> Result<Self, OrchestratorError> {
+        // Validate inputs
+        validate_bpm(bpm)?;
+        validate_timeline_notes(&notes, &tracks)?;
+        validate_tracks(&tracks)?;
+        if let Some(ref points) = control_points {
+            validate_control_points(points)?;
+        }
 
-        let mut total_duration_in_beats: f64 = 0.0;
-        for note in &self.notes {
-            total_duration_in_beats = total_duration_in_beats.max(note.start_time + note.duration);
+        // Extract ADSR values, defaulting sustain to 1.0, others to 0.0 if not provided
+        let adsr = adsr.unwrap_or((0.0, 0.0, 1.0, 0.0));
+        validate_adsr(adsr)?;
+        let (attack, decay, sustain, release) = adsr;
+
+        Ok(Self {
+            bpm,
+            notes,
+            tracks,
+            sections: Vec::new(),
+            length: None,
+            waveform: match control_points {
+                Some(points) => Waveform::Bezier(BezierWave::new(points, Shaping::default())),
+                None => Waveform::Sine,
+            },
+            voices: HashMap::new(),
+            tuning: Tuning::default(),
+            attack,
+            decay,
+            sustain,
+            release,
+        })
+    }
+
+    // Attaches the section layout of an arrangement so renders can report it
+    pub fn with_sections(mut self, sections: Vec<SectionMarker>) -> Self {
+        self.sections = sections;
+        self
+    }
+
+    // Fixes the song length in beats instead of ending after the last note
+    pub fn with_length(mut self, length: Option<f64>) -> Result<Self, OrchestratorError> {
+        if let Some(length) = length {
+            validate_song_length(length)?;
         }
+        self.length = length;
+        Ok(self)
+    }
 
-        // Add the release time to the total duration (for last note's release)
-        let total_duration_in_seconds = total_duration_in_beats * seconds_per_beat + self.release;
-        let total_samples: usize = (total_duration_in_seconds * sample_rate as f64).ceil() as usize;
+    // Closes the loop and removes the DC of the Bezier waveform, sine waves need neither
+    pub fn with_shaping(mut self, shaping: Shaping) -> Self {
+        if let Waveform::Bezier(wave) = &self.waveform {
+            self.waveform =
+                Waveform::Bezier(BezierWave::new(wave.control_points().clone(), shaping));
+        }
+        self
+    }
 
-        // Create a vector with specified capacity and with default value = 0 to avoid reallocations
-        // Creating it f64 because these samples are not clipped
-        // This acts like the timeline
-        let mut pcm_sample_sums: Vec<f64> = vec![0.0; total_samples];
+    // Plays the notes from a wavetable instead of a sine wave
+    pub fn with_wavetable(
+        mut self,
+        wave: Option<WavetableWave>,
+    ) -> Result<Self, OrchestratorError> {
+        let Some(wave) = wave else {
+            return Ok(self);
+        };
+        if !matches!(self.waveform, Waveform::Sine) {
+            return Err(OrchestratorError::ConflictingWaveform(self.waveform.name()));
+        }
+        validate_morph(wave.morph())?;
+        self.waveform = Waveform::Wavetable(wave);
+        Ok(self)
+    }
 
-        // Process each note and mix it at the same time
-        for note in &self.notes {
-            let wave = SinOscillator {
-                frequency: note.frequency()?,
-                amplitude: note.amplitude * CONDENSE_CONSTANT,
-                sample_rate: sample_rate,
-            };
+    // Plays the notes as sums of partials instead of a sine wave
+    pub fn with_partials(
+        mut self,
+        partials: Option<Vec<Partial>>,
+    ) -> Result<Self, OrchestratorError> {
+        let Some(partials) = partials else {
+            return Ok(self);
+        };
+        if !matches!(self.waveform, Waveform::Sine) {
+            return Err(OrchestratorError::ConflictingWaveform(self.waveform.name()));
+        }
+        validate_partials(&partials)?;
+        self.waveform = Waveform::Additive(partials);
+        Ok(self)
+    }
 
-            let start_sample = (note.start_time * seconds_per_beat * sample_rate as f64) as usize;
-            let samples_for_this_note =
-                ((note.duration + self.release) * seconds_per_beat * sample_rate as f64) as usize;
+    // Defines the voices notes and tracks can play, every voice they name must be in it
+    pub fn with_voices(
+        mut self,
+        voices: HashMap<String, SynthVoice>,
+    ) -> Result<Self, OrchestratorError> {
+        for voice in voices.values() {
+            validate_voice(voice)?;
+        }
+        self.voices = voices;
+        for (_, name) in self.pitched_notes() {
+            self.voice(name)?;
+        }
+        Ok(self)
+    }
 
-            let mut envelope = ADSREnvelope::new(
-                self.attack,
-                self.decay,
-                self.sustain,
-                self.release,
-                sample_rate,
-                (note.duration) * seconds_per_beat,
-            );
+    // Sets how note ids and octaves map to frequencies, every note must have a pitch in it
+    pub fn with_tuning(mut self, tuning: Tuning) -> Result<Self, OrchestratorError> {
+        for (note, _) in self.pitched_notes() {
+            validate_pitch(&note.pitch, &tuning)?;
+        }
+        self.tuning = tuning;
+        Ok(self)
+    }
 
-            for i in 0..samples_for_this_note {
-                let current_sample_index = start_sample + i;
-                if current_sample_index < total_samples {
-                    let raw_sample = wave.sample(i as u32);
-                    let processed_sample = envelope.apply(raw_sample, i as u32);
-                    pcm_sample_sums[current_sample_index] += processed_sample;
-                }
-            }
+    pub fn bpm(&self) -> u8 {
+        self.bpm
+    }
+
+    pub fn tuning(&self) -> &Tuning {
+        &self.tuning
+    }
+
+    pub fn notes(&self) -> &[TimelineNote] {
+        &self.notes
+    }
+
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    // Top-level notes and the notes of instrument tracks, with the name of the voice each plays
+    pub fn pitched_notes(&self) -> impl Iterator<Item = (&TimelineNote, Option<&str>)> {
+        let track_notes = self.tracks.iter().flat_map(|track| {
+            track
+                .pitched_notes()
+                .iter()
+                .map(|note| (note, note.voice().or(track.voice())))
+        });
+        self.notes
+            .iter()
+            .map(|note| (note, note.voice()))
+            .chain(track_notes)
+    }
+
+    pub fn voices(&self) -> &HashMap<String, SynthVoice> {
+        &self.voices
+    }
+
+    // Looks up the voice a note plays, `None` for the timeline's waveform
+    fn voice(&self, name: Option<&str>) -> Result<Option<&SynthVoice>, OrchestratorError> {
+        match name {
+            Some(name) => self
+                .voices
+                .get(name)
+                .map(Some)
+                .ok_or_else(|| OrchestratorError::UnknownVoice(name.to_string())),
+            None => Ok(None),
         }
+    }
 
-        // Apply soft clipping with tanh and convert to PCM
-        let pcm_samples: Vec<i16> = pcm_sample_sums
+    pub fn length(&self) -> Option<f64> {
+        self.length
+    }
+
+    // Release time of the envelope in seconds
+    pub fn release(&self) -> f64 {
+        self.release
+    }
+
+    // Label and sample range of every arranged section
+    pub fn section_ranges(&self, sample_rate: u32) -> Vec<(String, Range<usize>)> {
+        let seconds_per_beat = 60.0 / self.bpm as f64;
+        self.sections
             .iter()
-            .map(|&sum| {
-                // Apply soft clipping with tanh (sum is already normalized float)
-                let clipped = sum.tanh();
-                // Convert to PCM i16 range
-                (clipped * PCM_BIT_RANGE) as i16
+            .map(|section| {
+                (
+                    section.label(),
+                    section.sample_range(seconds_per_beat, sample_rate),
+                )
             })
-            .collect();
+            .collect()
+    }
 
-        Ok(pcm_samples)
+    pub fn waveform(&self) -> Waveform {
+        self.waveform.clone()
     }
-}
 
-pub struct TimelineBezierOrchestrator {
-    bpm: u8, //beats per min
-    notes: Vec<TimelineNote>,
-    control_points: Vec<f64>,
-    attack: f64,
-    decay: f64,
-    sustain: f64,
-    release: f64,
-}
+    pub fn note_count(&self) -> usize {
+        self.notes.len() + self.tracks.iter().map(Track::note_count).sum::<usize>()
+    }
 
-impl TimelineBezierOrchestrator {
-    pub fn pcm_samples(&self, sample_rate: u32) -> Result<Vec<i16>, OrchestratorError> {
+    // Builds the oscillator for a note using its voice, or the timeline's waveform
+    fn oscillator(
+        &self,
+        note: &TimelineNote,
+        voice: Option<&SynthVoice>,
+        seconds_per_beat: f64,
+        sample_rate: u32,
+    ) -> Result<Box<dyn Oscillator>, OrchestratorError> {
+        let frequency = note.frequency(&self.tuning)?;
+        let amplitude = note.amplitude * CONDENSE_CONSTANT;
+        if let Some(voice) = voice {
+            return Ok(voice.oscillator(
+                frequency,
+                amplitude,
+                note.amplitude,
+                sample_rate,
+                note.duration * seconds_per_beat,
+                note.start_time,
+            ));
+        }
+        match &self.waveform {
+            Waveform::Additive(partials) => Ok(Box::new(AdditiveOscillator::new(
+                frequency,
+                amplitude,
+                sample_rate,
+                partials,
+            ))),
+            Waveform::Wavetable(wave) => Ok(Box::new(WavetableOscillator::new(
+                frequency,
+                amplitude,
+                sample_rate,
+                wave.clone(),
+                note.duration * seconds_per_beat,
+            ))),
+            Waveform::Bezier(wave) => Ok(Box::new(BezierOscillator::new(
+                frequency,
+                amplitude,
+                sample_rate,
+                wave.clone(),
+            )?)),
+            Waveform::Sine => Ok(Box::new(SinOscillator {
+                frequency,
+                amplitude,
+                sample_rate,
+            })),
+        }
+    }
+
+    // Length of the rendered audio in seconds, including the last release tail
+    pub fn duration_in_seconds(&self) -> f64 {
         let seconds_per_beat = 60.0 / self.bpm as f64;
+        // A declared length wins, cutting off anything that plays past it
+        if let Some(length) = self.length {
+            return length * seconds_per_beat;
+        }
 
         let mut total_duration_in_beats: f64 = 0.0;
-        for note in &self.notes {
-            total_duration_in_beats = total_duration_in_beats.max(note.start_time + note.duration);
+        let mut total_duration_in_seconds: f64 = 0.0;
+        for (note, name) in self.pitched_notes() {
+            match self
+                .voice(name)
+                .ok()
+                .flatten()
+                .and_then(SynthVoice::release)
+            {
+                // Voices with envelopes of their own ring out for their own release
+                Some(release) => {
+                    total_duration_in_seconds = total_duration_in_seconds
+                        .max((note.start_time + note.duration) * seconds_per_beat + release)
+                }
+                None => {
+                    total_duration_in_beats =
+                        total_duration_in_beats.max(note.start_time + note.duration)
+                }
+            }
         }
 
         // Add the release time to the total duration (for last note's release)
-        let total_duration_in_seconds = total_duration_in_beats * seconds_per_beat + self.release;
+        total_duration_in_seconds = total_duration_in_seconds
+            .max(total_duration_in_beats * seconds_per_beat + self.release);
+        // Drum hits ring out on their own, so they may run past the last note
+        for track in &self.tracks {
+            total_duration_in_seconds =
+                total_duration_in_seconds.max(track.end_in_seconds(seconds_per_beat));
+        }
+        total_duration_in_seconds
+    }
+
+    pub fn pcm_samples(&self, sample_rate: u32) -> Result<Vec<i16>, OrchestratorError> {
+        self.pcm_samples_with_progress(sample_rate, &mut |_| {})
+    }
+
+    // Same as `pcm_samples`, calling `on_progress` as notes are mixed
+    pub fn pcm_samples_with_progress(
+        &self,
+        sample_rate: u32,
+        on_progress: &mut dyn FnMut(&Progress),
+    ) -> Result<Vec<i16>, OrchestratorError> {
+        let seconds_per_beat = 60.0 / self.bpm as f64;
+        let total_duration_in_seconds = self.duration_in_seconds();
         let total_samples: usize = (total_duration_in_seconds * sample_rate as f64).ceil() as usize;
 
+        let mut samples_to_mix = self
+            .tracks
+            .iter()
+            .map(|track| track.samples_to_mix(seconds_per_beat, sample_rate))
+            .sum::<usize>();
+        for (note, name) in self.pitched_notes() {
+            let voice = self.voice(name)?;
+            samples_to_mix += self.samples_for_note(note, voice, seconds_per_beat, sample_rate);
+        }
+        let mut progress = ProgressTracker::new(self.note_count(), samples_to_mix, on_progress);
+
         // Create a vector with specified capacity and with default value = 0 to avoid reallocations
         // Creating it f64 because these samples are not clipped
         // This acts like the timeline
         let mut pcm_sample_sums: Vec<f64> = vec![0.0; total_samples];
 
         // Process each note and mix it at the same time
-        for note in &self.notes {
-            let wave = BezierOscillator::new(
-                note.frequency()?,
-                note.amplitude * CONDENSE_CONSTANT,
-                sample_rate,
-                self.control_points.clone(),
-            )?;
+        for (note, name) in self.pitched_notes() {
+            let voice = self.voice(name)?;
+            let mut wave = self.oscillator(note, voice, seconds_per_beat, sample_rate)?;
 
             let start_sample = (note.start_time * seconds_per_beat * sample_rate as f64) as usize;
             let samples_for_this_note =
-                ((note.duration + self.release) * seconds_per_beat * sample_rate as f64) as usize;
-
-            let mut envelope = ADSREnvelope::new(
-                self.attack,
-                self.decay,
-                self.sustain,
-                self.release,
-                sample_rate,
-                (note.duration) * seconds_per_beat,
-            );
+                self.samples_for_note(note, voice, seconds_per_beat, sample_rate);
+
+            // Voices with envelopes of their own shape their notes themselves
+            let mut envelope = voice.and_then(SynthVoice::release).is_none().then(|| {
+                ADSREnvelope::new(
+                    self.attack,
+                    self.decay,
+                    self.sustain,
+                    self.release,
+                    sample_rate,
+                    (note.duration) * seconds_per_beat,
+                )
+            });
 
             for i in 0..samples_for_this_note {
                 let current_sample_index = start_sample + i;
                 if current_sample_index < total_samples {
                     let raw_sample = wave.sample(i as u32);
-                    let processed_sample = envelope.apply(raw_sample, i as u32);
+                    let processed_sample = match &mut envelope {
+                        Some(envelope) => envelope.apply(raw_sample, i as u32),
+                        None => raw_sample,
+                    };
                     pcm_sample_sums[current_sample_index] += processed_sample;
                 }
             }
+            progress.advance(1, samples_for_this_note);
+        }
+
+        for track in &self.tracks {
+            track.mix_into(
+                &mut pcm_sample_sums,
+                seconds_per_beat,
+                sample_rate,
+                &mut progress,
+            );
         }
 
         // Apply soft clipping with tanh and convert to PCM
@@ -246,7 +589,26 @@ impl TimelineBezierOrchestrator {
                 (clipped * PCM_BIT_RANGE) as i16
             })
             .collect();
+        progress.finish();
 
         Ok(pcm_samples)
     }
+
+    // Samples a note sounds for, including its release
+    fn samples_for_note(
+        &self,
+        note: &TimelineNote,
+        voice: Option<&SynthVoice>,
+        seconds_per_beat: f64,
+        sample_rate: u32,
+    ) -> usize {
+        match voice.and_then(SynthVoice::release) {
+            Some(release) => {
+                ((note.duration * seconds_per_beat + release) * sample_rate as f64) as usize
+            }
+            None => {
+                ((note.duration + self.release) * seconds_per_beat * sample_rate as f64) as usize
+            }
+        }
+    }
 }
@@ -0,0 +1,228 @@
+use crate::errors::OrchestratorError;
+use crate::orchestrator::note_name;
+use crate::scala::{KeyboardMap, Scale};
+use crate::validation::{validate_min_frequency, validate_reference_pitch};
+use serde::Deserialize;
+use std::borrow::Cow;
+
+/// Frequency of the reference key, A4 unless a keyboard mapping moves it,
+/// when the input doesn't set `reference_pitch`
+pub const DEFAULT_REFERENCE_PITCH: f64 = 440.0;
+/// Lowest pitch a note may have when the input doesn't set `min_frequency`, about MIDI note 0
+pub const DEFAULT_MIN_FREQUENCY: f64 = 8.0;
+
+const NOTES_PER_OCTAVE: usize = 12;
+const REFERENCE_ID: f64 = 9.0; // A
+const REFERENCE_OCTAVE: f64 = 4.0;
+const CENTS_PER_OCTAVE: f64 = 1200.0;
+
+/// Built-in tunings of the twelve notes of an octave
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Temperament {
+    Equal,
+    Just,
+    Pythagorean,
+    Meantone,
+}
+
+impl Temperament {
+    /// Scale of the temperament, equal temperament is the default and needs none
+    pub fn scale(self) -> Option<Scale> {
+        match self {
+            Temperament::Equal => None,
+            Temperament::Just => Some(Scale::just_intonation()),
+            Temperament::Pythagorean => Some(Scale::pythagorean()),
+            Temperament::Meantone => Some(Scale::meantone()),
+        }
+    }
+}
+
+/// Pitch fields shared by every kind of note
+/// A note names a key of the tuning with `id` and `octave`, or sets its `frequency` directly.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct Pitch {
+    id: Option<u8>,
+    octave: Option<u8>,
+    frequency: Option<f64>, // in Hz, replaces `id` and `octave`
+    #[serde(default)]
+    cents: f64, // detunes the note, 100 cents is a semitone
+}
+
+impl Pitch {
+    pub fn cents(&self) -> f64 {
+        self.cents
+    }
+
+    /// Whether the note sets its frequency instead of naming a key
+    pub fn is_raw(&self) -> bool {
+        self.frequency.is_some()
+    }
+
+    /// Scientific pitch name, e.g. `C#4`, or the frequency of raw-frequency notes
+    pub fn name(&self, tuning: &Tuning) -> String {
+        match (self.frequency, self.id, self.octave) {
+            (Some(frequency), _, _) => format!("{} Hz", frequency),
+            (None, Some(id), Some(octave)) => tuning.key_name(id, octave),
+            _ => "?".to_string(),
+        }
+    }
+}
+
+/// Turns a note's pitch into a frequency and checks it can be played
+/// Uses twelve-tone equal temperament with A4 at the reference pitch unless a scale is set.
+#[derive(Debug, Clone)]
+pub struct Tuning {
+    reference_pitch: f64, // in Hz, frequency of the reference key
+    scale: Option<Scale>,
+    keyboard: Option<KeyboardMap>, // consecutive keys play consecutive degrees when unset
+    min_frequency: f64,            // in Hz, lowest pitch allowed
+    nyquist: f64,                  // in Hz, pitches must stay below it
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            reference_pitch: DEFAULT_REFERENCE_PITCH,
+            scale: None,
+            keyboard: None,
+            min_frequency: DEFAULT_MIN_FREQUENCY,
+            nyquist: f64::INFINITY,
+        }
+    }
+}
+
+impl Tuning {
+    // Tunes the notes to a scale instead of equal temperament
+    pub fn with_scale(mut self, scale: Scale) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+
+    // Sets which key plays which scale degree, and the reference key and its frequency
+    pub fn with_keyboard_map(mut self, keyboard: KeyboardMap) -> Self {
+        self.reference_pitch = keyboard.reference_frequency();
+        self.scale.get_or_insert_with(Scale::equal_temperament);
+        self.keyboard = Some(keyboard);
+        self
+    }
+
+    // Sets the frequency of the reference key, replacing the one of the keyboard mapping
+    pub fn with_reference_pitch(mut self, reference_pitch: f64) -> Result<Self, OrchestratorError> {
+        validate_reference_pitch(reference_pitch)?;
+        self.reference_pitch = reference_pitch;
+        Ok(self)
+    }
+
+    // Keeps pitches below the Nyquist frequency of the render
+    pub fn with_sample_rate(mut self, sample_rate: u32) -> Self {
+        self.nyquist = sample_rate as f64 / 2.0;
+        self
+    }
+
+    // Sets the lowest pitch a note may have
+    pub fn with_min_frequency(mut self, min_frequency: f64) -> Result<Self, OrchestratorError> {
+        validate_min_frequency(min_frequency, self.nyquist)?;
+        self.min_frequency = min_frequency;
+        Ok(self)
+    }
+
+    pub fn nyquist(&self) -> f64 {
+        self.nyquist
+    }
+
+    pub fn reference_pitch(&self) -> f64 {
+        self.reference_pitch
+    }
+
+    /// Name of the scale, e.g. `Just intonation`
+    pub fn name(&self) -> &str {
+        match &self.scale {
+            Some(scale) => scale.description(),
+            None => "12-tone equal temperament",
+        }
+    }
+
+    /// Number of note ids in an octave, ids run from 0 to one less than this
+    pub fn keys_per_octave(&self) -> usize {
+        self.keyboard
+            .as_ref()
+            .and_then(KeyboardMap::keys)
+            .or(self.scale.as_ref().map(Scale::notes))
+            .unwrap_or(NOTES_PER_OCTAVE)
+    }
+
+    /// Name of the note tuned to the reference pitch, e.g. `A4`
+    pub fn reference_note(&self) -> String {
+        let keys = self.keys_per_octave() as i64;
+        let key = self.keyboard_map().reference_key();
+        match u8::try_from(key.div_euclid(keys) - 1) {
+            Ok(octave) if keys == NOTES_PER_OCTAVE as i64 => {
+                note_name(key.rem_euclid(keys) as u8, octave)
+            }
+            _ => format!("key {}", key),
+        }
+    }
+
+    // Name of a key, note names only fit octaves of twelve keys
+    fn key_name(&self, id: u8, octave: u8) -> String {
+        if self.keys_per_octave() == NOTES_PER_OCTAVE {
+            note_name(id, octave)
+        } else {
+            format!("degree {}, octave {}", id, octave)
+        }
+    }
+
+    /// Frequency of a note, which must be between the lowest pitch and Nyquist
+    pub fn frequency(&self, pitch: &Pitch) -> Result<f64, OrchestratorError> {
+        let frequency = match (pitch.frequency, pitch.id, pitch.octave) {
+            (Some(frequency), None, None) => frequency * 2_f64.powf(pitch.cents / CENTS_PER_OCTAVE),
+            (Some(_), _, _) => return Err(OrchestratorError::ConflictingPitch),
+            (None, Some(id), Some(octave)) => self.key_frequency(id, octave, pitch.cents)?,
+            (None, _, _) => return Err(OrchestratorError::MissingPitch),
+        };
+        if !(self.min_frequency..self.nyquist).contains(&frequency) {
+            return Err(OrchestratorError::InvalidFrequency {
+                frequency,
+                floor: self.min_frequency,
+                nyquist: self.nyquist,
+            });
+        }
+        Ok(frequency)
+    }
+
+    // Frequency of a key of the tuning
+    fn key_frequency(&self, id: u8, octave: u8, cents: f64) -> Result<f64, OrchestratorError> {
+        let keys = self.keys_per_octave();
+        if id as usize >= keys {
+            return Err(OrchestratorError::InvalidNoteId { id, keys });
+        }
+        let Some(scale) = &self.scale else {
+            let semitones = (id as f64 - REFERENCE_ID)
+                + NOTES_PER_OCTAVE as f64 * (octave as f64 - REFERENCE_OCTAVE);
+            let octaves = semitones / NOTES_PER_OCTAVE as f64 + cents / CENTS_PER_OCTAVE;
+            return Ok(self.reference_pitch * 2_f64.powf(octaves));
+        };
+
+        let keyboard = self.keyboard_map();
+        let key = (octave as i64 + 1) * keys as i64 + id as i64;
+        let degree = keyboard
+            .degree(key)
+            .ok_or(OrchestratorError::UnmappedNote { id, octave })?;
+        // Keyboard mappings are only accepted when their reference key is mapped
+        let reference = keyboard.degree(keyboard.reference_key()).unwrap_or(0);
+        let offset = scale.cents(degree) - scale.cents(reference) + cents;
+        Ok(self.reference_pitch * 2_f64.powf(offset / CENTS_PER_OCTAVE))
+    }
+
+    // The keyboard mapping, or the linear one Scala uses when there is none
+    fn keyboard_map(&self) -> Cow<'_, KeyboardMap> {
+        match &self.keyboard {
+            Some(keyboard) => Cow::Borrowed(keyboard),
+            None => Cow::Owned(KeyboardMap::linear(
+                self.keys_per_octave(),
+                self.reference_pitch,
+            )),
+        }
+    }
+}
@@ -1,6 +1,25 @@
+use crate::bezier::ControlPoints;
 use crate::errors::OrchestratorError;
+use crate::fm::{FmVoice, MAX_OPERATORS, MIN_OPERATORS, Operator};
 use crate::orchestrator::Note;
-use crate::timeline_orchestrator::TimelineNote;
+use crate::oscillator::Partial;
+use crate::percussion::PercussionNote;
+use crate::pluck::PluckVoice;
+use crate::timeline_orchestrator::{TimelineNote, Track};
+use crate::tuning::{Pitch, Tuning};
+use crate::voice::SynthVoice;
+use crate::wavetable::Morph;
+
+/// A problem with a single field, named as the field appears in the JSON input
+pub type FieldProblem = (&'static str, OrchestratorError);
+
+// Turns a list of collected problems back into a fail-fast result
+fn first_problem(problems: Vec<FieldProblem>) -> Result<(), OrchestratorError> {
+    match problems.into_iter().next() {
+        Some((_, error)) => Err(error),
+        None => Ok(()),
+    }
+}
 
 /// Validates BPM is within reasonable range (1-240)
 pub fn validate_bpm(bpm: u8) -> Result<(), OrchestratorError> {
@@ -10,41 +29,158 @@ pub fn validate_bpm(bpm: u8) -> Result<(), OrchestratorError> {
     Ok(())
 }
 
+/// Validates a note has a pitch in the tuning and that it can be played
+/// Keys must be one of the notes of an octave and mapped by the keyboard mapping,
+/// and the frequency must be between the tuning's floor and Nyquist.
+pub fn validate_pitch(pitch: &Pitch, tuning: &Tuning) -> Result<(), OrchestratorError> {
+    tuning.frequency(pitch).map(|_| ())
+}
+
+/// Validates amplitude (0.0 to 1.0)
+pub fn validate_amplitude(amplitude: f64) -> Result<(), OrchestratorError> {
+    if !(0.0..=1.0).contains(&amplitude) {
+        return Err(OrchestratorError::InvalidAmplitude(amplitude));
+    }
+    Ok(())
+}
+
+/// Validates a cents offset is a finite number
+pub fn validate_cents(cents: f64) -> Result<(), OrchestratorError> {
+    if !cents.is_finite() {
+        return Err(OrchestratorError::InvalidCents(cents));
+    }
+    Ok(())
+}
+
+/// Validates the frequency of A4 is a finite, positive number of Hz
+pub fn validate_reference_pitch(reference_pitch: f64) -> Result<(), OrchestratorError> {
+    if !reference_pitch.is_finite() || reference_pitch <= 0.0 {
+        return Err(OrchestratorError::InvalidReferencePitch(reference_pitch));
+    }
+    Ok(())
+}
+
+/// Validates the lowest allowed pitch is a finite, positive number of Hz below Nyquist
+pub fn validate_min_frequency(min_frequency: f64, nyquist: f64) -> Result<(), OrchestratorError> {
+    if !min_frequency.is_finite() || min_frequency <= 0.0 || min_frequency >= nyquist {
+        return Err(OrchestratorError::InvalidMinFrequency(min_frequency));
+    }
+    Ok(())
+}
+
+/// Validates a note starts at a finite, non-negative beat
+pub fn validate_start_time(index: usize, start_time: f64) -> Result<(), OrchestratorError> {
+    if !start_time.is_finite() || start_time < 0.0 {
+        return Err(OrchestratorError::InvalidNoteTiming {
+            index,
+            field: "start_time",
+            value: start_time,
+            reason: "Start time must be a finite number of beats, 0 or more",
+        });
+    }
+    Ok(())
+}
+
+/// Validates a note length (`duration` or `beats`) is a finite, positive number of beats
+pub fn validate_length(
+    index: usize,
+    field: &'static str,
+    length: f64,
+) -> Result<(), OrchestratorError> {
+    if !length.is_finite() || length <= 0.0 {
+        return Err(OrchestratorError::InvalidNoteTiming {
+            index,
+            field,
+            value: length,
+            reason: "Length must be a finite number of beats greater than 0",
+        });
+    }
+    Ok(())
+}
+
+/// Validates a declared song length is a finite, positive number of beats
+pub fn validate_song_length(length: f64) -> Result<(), OrchestratorError> {
+    if !length.is_finite() || length <= 0.0 {
+        return Err(OrchestratorError::InvalidSongLength(length));
+    }
+    Ok(())
+}
+
+/// Validates the ADSR envelope values
+pub fn validate_adsr(adsr: (f64, f64, f64, f64)) -> Result<(), OrchestratorError> {
+    first_problem(check_adsr(adsr))
+}
+
+/// Checks every ADSR value and returns all problems found
+/// Times must be finite and not negative, sustain is a level between 0.0 and 1.0
+pub fn check_adsr((attack, decay, sustain, release): (f64, f64, f64, f64)) -> Vec<FieldProblem> {
+    let mut problems = Vec::new();
+    for (field, value) in [("attack", attack), ("decay", decay), ("release", release)] {
+        if !value.is_finite() || value < 0.0 {
+            problems.push((
+                field,
+                OrchestratorError::InvalidEnvelope {
+                    field,
+                    value,
+                    reason: "Times must be a finite number of seconds, 0 or more",
+                },
+            ));
+        }
+    }
+    if !(0.0..=1.0).contains(&sustain) {
+        problems.push((
+            "sustain",
+            OrchestratorError::InvalidEnvelope {
+                field: "sustain",
+                value: sustain,
+                reason: "Sustain level must be between 0.0 and 1.0",
+            },
+        ));
+    }
+    problems
+}
+
 /// Validates that notes array is not empty and each note is valid
 pub fn validate_notes(notes: &[Note]) -> Result<(), OrchestratorError> {
     if notes.is_empty() {
         return Err(OrchestratorError::EmptyNotes);
     }
 
-    for note in notes.iter() {
-        validate_note(note)?;
+    for (index, note) in notes.iter().enumerate() {
+        validate_note(index, note)?;
     }
 
     Ok(())
 }
 
 /// Validates a single note's properties
-pub fn validate_note(note: &Note) -> Result<(), OrchestratorError> {
-    // Validate note ID (0-11 for 12 chromatic notes)
-    if note.id() > 11 {
-        return Err(OrchestratorError::InvalidNoteId(note.id()));
-    }
+pub fn validate_note(index: usize, note: &Note) -> Result<(), OrchestratorError> {
+    first_problem(check_note(index, note))
+}
 
-    // Validate octave (0-8 is standard piano range)
-    if note.octave() > 8 {
-        return Err(OrchestratorError::InvalidOctave(note.octave()));
+/// Checks every property of a note and returns all problems found
+/// The pitch depends on the tuning and is checked with `validate_pitch`.
+pub fn check_note(index: usize, note: &Note) -> Vec<FieldProblem> {
+    let mut problems = Vec::new();
+    if let Err(e) = validate_amplitude(note.amplitude()) {
+        problems.push(("amplitude", e));
     }
-
-    // Validate amplitude (0.0 to 1.0)
-    if note.amplitude() < 0.0 || note.amplitude() > 1.0 {
-        return Err(OrchestratorError::InvalidAmplitude(note.amplitude()));
+    if let Err(e) = validate_cents(note.pitch().cents()) {
+        problems.push(("cents", e));
     }
-
-    Ok(())
+    if let Err(e) = validate_length(index, "beats", note.beats()) {
+        problems.push(("beats", e));
+    }
+    problems
 }
 
 /// Validates control points for Bezier oscillator
-pub fn validate_control_points(points: &[f64]) -> Result<(), OrchestratorError> {
+pub fn validate_control_points(control_points: &ControlPoints) -> Result<(), OrchestratorError> {
+    let points = match control_points {
+        ControlPoints::Amplitudes(points) => points,
+        ControlPoints::Points(points) => return validate_bezier_segments(points),
+    };
+
     // Must have exactly 4 control points
     if points.len() != 4 {
         return Err(OrchestratorError::InvalidControlPoints(format!(
@@ -55,7 +191,7 @@ pub fn validate_control_points(points: &[f64]) -> Result<(), OrchestratorError>
 
     // Each control point must be in range [-1.0, 1.0]
     for (index, &point) in points.iter().enumerate() {
-        if point < -1.0 || point > 1.0 {
+        if !(-1.0..=1.0).contains(&point) {
             return Err(OrchestratorError::InvalidControlPoints(format!(
                 "Control point {} has value {}, must be between -1.0 and 1.0",
                 index, point
@@ -66,35 +202,371 @@ pub fn validate_control_points(points: &[f64]) -> Result<(), OrchestratorError>
     Ok(())
 }
 
-/// Validates that timeline notes array is not empty and each note is valid
-pub fn validate_timeline_notes(notes: &[TimelineNote]) -> Result<(), OrchestratorError> {
-    if notes.is_empty() {
+/// Validates `[phase, amplitude]` points of a multi-segment Bézier waveform
+/// The cycle starts at phase 0.0 and ends at 1.0, and phases never go back within a
+/// segment so every phase has a single amplitude. Equal phases make sharp edges.
+pub fn validate_bezier_segments(points: &[[f64; 2]]) -> Result<(), OrchestratorError> {
+    if points.len() < 4 || !(points.len() - 1).is_multiple_of(3) {
+        return Err(OrchestratorError::InvalidControlPoints(format!(
+            "Expected a start point and 3 points per segment (4, 7, 10, ...), got {}",
+            points.len()
+        )));
+    }
+
+    for (index, &[phase, amplitude]) in points.iter().enumerate() {
+        if !(0.0..=1.0).contains(&phase) {
+            return Err(OrchestratorError::InvalidControlPoints(format!(
+                "Point {} has phase {}, must be between 0.0 and 1.0",
+                index, phase
+            )));
+        }
+        if !(-1.0..=1.0).contains(&amplitude) {
+            return Err(OrchestratorError::InvalidControlPoints(format!(
+                "Point {} has amplitude {}, must be between -1.0 and 1.0",
+                index, amplitude
+            )));
+        }
+        if index > 0 && phase < points[index - 1][0] {
+            return Err(OrchestratorError::InvalidControlPoints(format!(
+                "Point {} has phase {}, phases must not decrease",
+                index, phase
+            )));
+        }
+    }
+
+    let last = points[points.len() - 1][0];
+    if points[0][0] != 0.0 || last != 1.0 {
+        return Err(OrchestratorError::InvalidControlPoints(format!(
+            "The waveform must run from phase 0.0 to 1.0, got {} to {}",
+            points[0][0], last
+        )));
+    }
+
+    Ok(())
+}
+
+/// Validates the partials of an additive voice
+pub fn validate_partials(partials: &[Partial]) -> Result<(), OrchestratorError> {
+    if partials.is_empty() {
+        return Err(OrchestratorError::EmptyPartials);
+    }
+    for (index, partial) in partials.iter().enumerate() {
+        first_problem(check_partial(index, partial))?;
+    }
+    Ok(())
+}
+
+/// Checks the ratio, amplitude and decay of a partial and returns all problems found
+pub fn check_partial(index: usize, partial: &Partial) -> Vec<FieldProblem> {
+    let mut problems = Vec::new();
+    let mut check = |field, value: f64, valid: bool, reason| {
+        if !valid {
+            problems.push((
+                field,
+                OrchestratorError::InvalidPartial {
+                    index,
+                    field,
+                    value,
+                    reason,
+                },
+            ));
+        }
+    };
+    check(
+        "ratio",
+        partial.ratio,
+        partial.ratio.is_finite() && partial.ratio > 0.0,
+        "Ratios must be a finite multiple of the note's pitch greater than 0",
+    );
+    check(
+        "amplitude",
+        partial.amplitude,
+        (0.0..=1.0).contains(&partial.amplitude),
+        "Amplitude must be between 0.0 and 1.0",
+    );
+    if let Some(decay) = partial.decay {
+        check(
+            "decay",
+            decay,
+            decay.is_finite() && decay > 0.0,
+            "Decay must be a finite number of seconds greater than 0",
+        );
+    }
+    problems
+}
+
+/// Validates a wavetable frame has at least 2 samples
+pub fn validate_frame_size(frame_size: usize) -> Result<(), OrchestratorError> {
+    if frame_size < 2 {
+        return Err(OrchestratorError::InvalidWavetable(
+            "a frame needs at least 2 samples".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Validates how a wavetable's position moves
+pub fn validate_morph(morph: &Morph) -> Result<(), OrchestratorError> {
+    first_problem(check_morph(morph))?;
+    if let Some(envelope) = morph.envelope {
+        validate_adsr(envelope.adsr.values())?;
+    }
+    Ok(())
+}
+
+/// Checks the position, envelope amount and LFO of a wavetable and returns all problems found
+/// Positions run from 0.0 (first frame) to 1.0 (last frame).
+pub fn check_morph(morph: &Morph) -> Vec<FieldProblem> {
+    let mut problems = Vec::new();
+    if !(0.0..=1.0).contains(&morph.position) {
+        problems.push((
+            "position",
+            OrchestratorError::InvalidWavetable(format!(
+                "position must be between 0.0 and 1.0, got {}",
+                morph.position
+            )),
+        ));
+    }
+    if let Some(envelope) = morph.envelope
+        && !(-1.0..=1.0).contains(&envelope.amount)
+    {
+        problems.push((
+            "envelope",
+            OrchestratorError::InvalidWavetable(format!(
+                "envelope amount must be between -1.0 and 1.0, got {}",
+                envelope.amount
+            )),
+        ));
+    }
+    if let Some(lfo) = morph.lfo {
+        if !lfo.rate.is_finite() || lfo.rate <= 0.0 {
+            problems.push((
+                "lfo",
+                OrchestratorError::InvalidWavetable(format!(
+                    "LFO rate must be a finite number of Hz above 0, got {}",
+                    lfo.rate
+                )),
+            ));
+        }
+        if !(0.0..=1.0).contains(&lfo.depth) {
+            problems.push((
+                "lfo",
+                OrchestratorError::InvalidWavetable(format!(
+                    "LFO depth must be between 0.0 and 1.0, got {}",
+                    lfo.depth
+                )),
+            ));
+        }
+    }
+    problems
+}
+
+/// Validates a voice from the top-level `voices` object
+pub fn validate_voice(voice: &SynthVoice) -> Result<(), OrchestratorError> {
+    match voice {
+        SynthVoice::Fm(fm) => validate_fm_voice(fm),
+        SynthVoice::Pluck(pluck) => first_problem(check_pluck_voice(pluck)),
+        // Zones are checked as their files are read
+        SynthVoice::Sampler(_) | SynthVoice::SoundFont(_) => Ok(()),
+    }
+}
+
+/// Validates the operator count and every operator of an FM voice
+pub fn validate_fm_voice(voice: &FmVoice) -> Result<(), OrchestratorError> {
+    validate_operator_count(voice.operators.len())?;
+    for (index, operator) in voice.operators.iter().enumerate() {
+        first_problem(check_operator(index, operator))?;
+        if let Some(envelope) = operator.envelope {
+            validate_adsr(envelope.values())?;
+        }
+    }
+    Ok(())
+}
+
+/// Validates a sampler has at least one zone
+pub fn validate_zone_count(count: usize) -> Result<(), OrchestratorError> {
+    if count == 0 {
+        return Err(OrchestratorError::InvalidSampler(
+            "a sampler needs at least one zone".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Validates an FM voice has between 2 and 6 operators
+pub fn validate_operator_count(count: usize) -> Result<(), OrchestratorError> {
+    if !(MIN_OPERATORS..=MAX_OPERATORS).contains(&count) {
+        return Err(OrchestratorError::InvalidOperatorCount(count));
+    }
+    Ok(())
+}
+
+/// Checks the ratio, level and modulation index of an FM operator and returns all problems found
+/// The envelope is checked with `check_adsr`.
+pub fn check_operator(index: usize, operator: &Operator) -> Vec<FieldProblem> {
+    let mut problems = Vec::new();
+    let mut check = |field, value: f64, valid: bool, reason| {
+        if !valid {
+            problems.push((
+                field,
+                OrchestratorError::InvalidOperator {
+                    index,
+                    field,
+                    value,
+                    reason,
+                },
+            ));
+        }
+    };
+    check(
+        "ratio",
+        operator.ratio,
+        operator.ratio.is_finite() && operator.ratio > 0.0,
+        "Ratios must be a finite multiple of the note's pitch greater than 0",
+    );
+    check(
+        "level",
+        operator.level,
+        (0.0..=1.0).contains(&operator.level),
+        "Level must be between 0.0 and 1.0",
+    );
+    check(
+        "index",
+        operator.index,
+        operator.index.is_finite() && operator.index >= 0.0,
+        "Modulation index must be a finite number of radians, 0 or more",
+    );
+    problems
+}
+
+/// Checks the damping, brightness, pick position and release of a plucked string
+/// and returns all problems found
+pub fn check_pluck_voice(voice: &PluckVoice) -> Vec<FieldProblem> {
+    let mut problems = Vec::new();
+    let mut check = |field, value: f64, valid: bool, reason| {
+        if !valid {
+            problems.push((
+                field,
+                OrchestratorError::InvalidPluck {
+                    field,
+                    value,
+                    reason,
+                },
+            ));
+        }
+    };
+    check(
+        "damping",
+        voice.damping,
+        (0.0..=1.0).contains(&voice.damping),
+        "Damping must be between 0.0 and 1.0",
+    );
+    check(
+        "brightness",
+        voice.brightness,
+        (0.0..=1.0).contains(&voice.brightness),
+        "Brightness must be between 0.0 and 1.0",
+    );
+    if let Some(position) = voice.pick_position {
+        check(
+            "pick_position",
+            position,
+            position > 0.0 && position < 1.0,
+            "Pick position must be between the ends of the string, above 0.0 and below 1.0",
+        );
+    }
+    check(
+        "release",
+        voice.release,
+        voice.release.is_finite() && voice.release >= 0.0,
+        "Release must be a finite number of seconds, 0 or more",
+    );
+    problems
+}
+
+/// Validates that the timeline has something to play and each note is valid
+/// The top-level notes may be empty when the tracks provide the notes instead
+pub fn validate_timeline_notes(
+    notes: &[TimelineNote],
+    tracks: &[Track],
+) -> Result<(), OrchestratorError> {
+    if notes.is_empty() && tracks.iter().all(|track| track.note_count() == 0) {
         return Err(OrchestratorError::EmptyNotes);
     }
 
-    for note in notes.iter() {
-        validate_timeline_note(note)?;
+    for (index, note) in notes.iter().enumerate() {
+        validate_timeline_note(index, note)?;
     }
 
     Ok(())
 }
 
 /// Validates a single timeline note's properties
-pub fn validate_timeline_note(note: &TimelineNote) -> Result<(), OrchestratorError> {
-    // Validate note ID (0-11 for 12 chromatic notes)
-    if note.id() > 11 {
-        return Err(OrchestratorError::InvalidNoteId(note.id()));
-    }
+pub fn validate_timeline_note(index: usize, note: &TimelineNote) -> Result<(), OrchestratorError> {
+    first_problem(check_timeline_note(index, note))
+}
 
-    // Validate octave (0-8 is standard piano range)
-    if note.octave() > 8 {
-        return Err(OrchestratorError::InvalidOctave(note.octave()));
+/// Checks every property of a timeline note and returns all problems found
+/// The pitch depends on the tuning and is checked with `validate_pitch`.
+pub fn check_timeline_note(index: usize, note: &TimelineNote) -> Vec<FieldProblem> {
+    let mut problems = Vec::new();
+    if let Err(e) = validate_amplitude(note.amplitude()) {
+        problems.push(("amplitude", e));
     }
+    if let Err(e) = validate_cents(note.pitch().cents()) {
+        problems.push(("cents", e));
+    }
+    if let Err(e) = validate_start_time(index, note.start_time()) {
+        problems.push(("start_time", e));
+    }
+    if let Err(e) = validate_length(index, "duration", note.duration()) {
+        problems.push(("duration", e));
+    }
+    problems
+}
 
-    // Validate amplitude (0.0 to 1.0)
-    if note.amplitude() < 0.0 || note.amplitude() > 1.0 {
-        return Err(OrchestratorError::InvalidAmplitude(note.amplitude()));
+/// Validates every note of every extra timeline track
+pub fn validate_tracks(tracks: &[Track]) -> Result<(), OrchestratorError> {
+    for track in tracks.iter() {
+        match track {
+            Track::Percussion(percussion) => {
+                for (index, note) in percussion.notes().iter().enumerate() {
+                    validate_percussion_note(index, note)?;
+                }
+            }
+            Track::Instrument(instrument) => {
+                for (index, note) in instrument.notes().iter().enumerate() {
+                    validate_timeline_note(index, note)?;
+                }
+            }
+        }
     }
 
     Ok(())
 }
+
+/// Validates a single drum hit's properties
+pub fn validate_percussion_note(
+    index: usize,
+    note: &PercussionNote,
+) -> Result<(), OrchestratorError> {
+    first_problem(check_percussion_note(index, note))
+}
+
+/// Checks every property of a drum hit and returns all problems found
+pub fn check_percussion_note(index: usize, note: &PercussionNote) -> Vec<FieldProblem> {
+    let mut problems = Vec::new();
+    if let Err(e) = validate_amplitude(note.amplitude()) {
+        problems.push(("amplitude", e));
+    }
+    if let Err(e) = validate_start_time(index, note.start_time()) {
+        problems.push(("start_time", e));
+    }
+    if let Some(Err(e)) = note
+        .duration()
+        .map(|duration| validate_length(index, "duration", duration))
+    {
+        problems.push(("duration", e));
+    }
+    problems
+}
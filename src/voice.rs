@@ -0,0 +1,138 @@
+use crate::errors::OrchestratorError;
+use crate::fm::{FmOscillator, FmVoice};
+use crate::oscillator::Oscillator;
+use crate::pattern::{PitchedInstrument, StepPattern};
+use crate::pluck::{PluckOscillator, PluckVoice};
+use crate::sampler::{Sampler, SamplerOscillator};
+use crate::timeline_orchestrator::TimelineNote;
+use serde::Deserialize;
+
+/// An instrument defined by name in the top-level `voices` object, with its files read
+/// Notes play it by naming it in their `voice` field, or by sitting on an instrument
+/// track that does.
+#[derive(Debug, Clone)]
+pub enum SynthVoice {
+    Fm(FmVoice),
+    Pluck(PluckVoice),
+    Sampler(Sampler),
+    SoundFont(Sampler), // a preset of a SoundFont 2 file, read into layered zones
+}
+
+impl SynthVoice {
+    // Name of the voice type as written in the input
+    pub fn kind(&self) -> &'static str {
+        match self {
+            SynthVoice::Fm(_) => "fm",
+            SynthVoice::Pluck(_) => "pluck",
+            SynthVoice::Sampler(_) => "sampler",
+            SynthVoice::SoundFont(_) => "soundfont",
+        }
+    }
+
+    /// Seconds a note keeps sounding after note-off
+    /// `None` for voices the top-level `adsr` shapes, whose notes release with it. Voices
+    /// with envelopes of their own ignore it.
+    pub fn release(&self) -> Option<f64> {
+        match self {
+            SynthVoice::Fm(voice) => Some(voice.release()),
+            SynthVoice::Pluck(voice) => Some(voice.release),
+            SynthVoice::Sampler(sampler) | SynthVoice::SoundFont(sampler) => sampler.release(),
+        }
+    }
+
+    // `velocity` is the note's amplitude from 0.0 to 1.0, `duration` the time in seconds
+    // before the note is released and `start_time` when the note starts, in beats
+    pub fn oscillator(
+        &self,
+        frequency: f64,
+        amplitude: f64,
+        velocity: f64,
+        sample_rate: u32,
+        duration: f64,
+        start_time: f64,
+    ) -> Box<dyn Oscillator> {
+        match self {
+            SynthVoice::Fm(voice) => Box::new(FmOscillator::new(
+                frequency,
+                amplitude,
+                sample_rate,
+                voice.clone(),
+                duration,
+            )),
+            SynthVoice::Pluck(voice) => Box::new(PluckOscillator::new(
+                frequency,
+                amplitude,
+                sample_rate,
+                *voice,
+                duration,
+                start_time,
+            )),
+            SynthVoice::Sampler(sampler) | SynthVoice::SoundFont(sampler) => {
+                Box::new(SamplerOscillator::new(
+                    frequency,
+                    amplitude,
+                    velocity,
+                    sample_rate,
+                    sampler,
+                    duration,
+                ))
+            }
+        }
+    }
+}
+
+/// Track of pitched notes played by a voice
+/// Notes without a `voice` of their own play the track's, or the timeline's waveform
+/// when the track doesn't name one either.
+#[derive(Debug, Deserialize, Clone)]
+pub struct InstrumentTrack {
+    voice: Option<String>,
+    #[serde(default)]
+    notes: Vec<TimelineNote>,
+    #[serde(default)]
+    patterns: Vec<StepPattern<PitchedInstrument>>,
+}
+
+impl InstrumentTrack {
+    pub fn voice(&self) -> Option<&str> {
+        self.voice.as_deref()
+    }
+
+    pub fn notes(&self) -> &[TimelineNote] {
+        &self.notes
+    }
+
+    pub fn patterns(&self) -> &[StepPattern<PitchedInstrument>] {
+        &self.patterns
+    }
+
+    // Turns the track's step patterns into regular notes
+    pub fn expand_patterns(&mut self) -> Result<(), OrchestratorError> {
+        for pattern in self.patterns.drain(..) {
+            self.notes.extend(pattern.expand()?);
+        }
+        Ok(())
+    }
+
+    pub fn shifted(&self, beats: f64) -> Self {
+        Self {
+            notes: self.notes.iter().map(|note| note.shifted(beats)).collect(),
+            ..self.clone()
+        }
+    }
+
+    pub fn end_in_beats(&self) -> f64 {
+        self.notes
+            .iter()
+            .map(|note| note.start_time() + note.duration())
+            .fold(0.0, f64::max)
+    }
+
+    // End of the grids of the step patterns not expanded yet
+    pub fn patterns_end_in_beats(&self) -> f64 {
+        self.patterns
+            .iter()
+            .map(StepPattern::end_in_beats)
+            .fold(0.0, f64::max)
+    }
+}
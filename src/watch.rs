@@ -0,0 +1,150 @@
+use crate::batch::{self, RenderStats};
+use crate::errors::OrchestratorError;
+use crate::feedback;
+use crate::include;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// How often the watched files are checked for changes
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// Re-renders the input every time it or a file it includes changes, until interrupted
+/// Invalid edits are reported and the last good render is kept.
+pub fn watch(input_file: &Path, output_path: &Path, sample_rate: u32) -> Result<()> {
+    let mut files = source_files(input_file, &[]);
+    let mut stamps = modified_times(&files);
+    let mut last = rebuild(input_file, output_path, sample_rate, &Snapshot::default());
+    feedback::info(&format!(
+        "Watching {} ({} files) for changes, press Ctrl+C to stop",
+        input_file.display(),
+        files.len()
+    ));
+
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        if modified_times(&files) == stamps {
+            continue;
+        }
+
+        // Includes may have been added or removed by the edit
+        files = source_files(input_file, &files);
+        stamps = modified_times(&files);
+        last = rebuild(input_file, output_path, sample_rate, &last);
+    }
+}
+
+/// What the last attempt produced, kept to describe what an edit changed
+#[derive(Default)]
+struct Snapshot {
+    rendered: Option<(usize, f64)>, // note count and duration in seconds of the last good render
+    errors: usize,                  // problems found by the last attempt
+}
+
+// Renders the input once and reports how it differs from the last attempt
+fn rebuild(input_file: &Path, output_path: &Path, sample_rate: u32, last: &Snapshot) -> Snapshot {
+    // The summary printed below replaces the usual loading messages
+    let outcome = feedback::quietly(|| batch::render_file(input_file, output_path, sample_rate));
+
+    match outcome {
+        Ok(stats) => {
+            feedback::record(
+                &stats,
+                &format!(
+                    "Rendered {}: {}",
+                    output_path.display(),
+                    describe(last, &stats)
+                ),
+            );
+            Snapshot {
+                rendered: Some((stats.notes, stats.duration)),
+                errors: 0,
+            }
+        }
+        Err(error) => {
+            let errors = report(&error);
+            let kept = if last.rendered.is_some() {
+                "keeping the last render until the input is fixed"
+            } else {
+                "nothing rendered yet"
+            };
+            feedback::warning(&format!("{} error(s), {}", errors, kept));
+            Snapshot {
+                rendered: last.rendered,
+                errors,
+            }
+        }
+    }
+}
+
+// Summarises what changed since the last attempt
+fn describe(last: &Snapshot, stats: &RenderStats) -> String {
+    let fixed = if last.errors > 0 {
+        format!(", {} error(s) fixed", last.errors)
+    } else {
+        String::new()
+    };
+    let Some((notes, duration)) = last.rendered else {
+        return format!("{} notes, {:.2}s{}", stats.notes, stats.duration, fixed);
+    };
+
+    let notes = if notes == stats.notes {
+        format!("{} notes", stats.notes)
+    } else {
+        format!("notes {} → {}", notes, stats.notes)
+    };
+    let duration = if (duration - stats.duration).abs() < 1e-9 {
+        format!("{:.2}s", stats.duration)
+    } else {
+        format!("duration {:.2}s → {:.2}s", duration, stats.duration)
+    };
+    format!("{}, {}{}", notes, duration, fixed)
+}
+
+// Prints the problems behind a failed render and returns how many there were
+fn report(error: &anyhow::Error) -> usize {
+    if let Some(OrchestratorError::InvalidInput(problems)) = error.downcast_ref() {
+        for problem in problems {
+            feedback::error(&problem.to_string());
+        }
+        problems.len()
+    } else {
+        feedback::error(&format!("{:#}", error));
+        1
+    }
+}
+
+// The input and every file it includes
+// Files that were watched before are kept, so a broken include is still noticed once fixed.
+fn source_files(input_file: &Path, previous: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = vec![input_file.to_path_buf()];
+    if let Ok(included) = included_files(input_file) {
+        files.extend(included);
+    }
+    for file in previous {
+        if !files.contains(file) {
+            files.push(file.clone());
+        }
+    }
+    files
+}
+
+fn included_files(input_file: &Path) -> Result<Vec<PathBuf>> {
+    let data = std::fs::read_to_string(input_file)?;
+    let value = serde_json::from_str(&data)?;
+    let resolved =
+        include::resolve_includes(value, input_file).context("Failed to resolve includes")?;
+    Ok(resolved.files)
+}
+
+// Last modification time of every file, missing files have none
+fn modified_times(files: &[PathBuf]) -> Vec<Option<SystemTime>> {
+    files
+        .iter()
+        .map(|file| {
+            std::fs::metadata(file)
+                .and_then(|meta| meta.modified())
+                .ok()
+        })
+        .collect()
+}
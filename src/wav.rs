@@ -1,8 +1,141 @@
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use std::fs::{self, File};
 use std::io::Write;
+use std::ops::Range;
 use std::path::Path;
 
+// Audio format codes of the fmt chunk
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE; // the real code starts the sub-format GUID
+
+// Size of the smpl chunk before its loops, and of each loop
+const SAMPLER_HEADER: usize = 36;
+const SAMPLER_LOOP: usize = 24;
+
+/// Audio read from a WAV file, mixed down to mono
+pub struct Recording {
+    pub samples: Vec<f64>, // -1.0 to 1.0
+    pub sample_rate: u32,
+    pub root_key: Option<f64>, // MIDI key the sample plays at its recorded speed, from the smpl chunk
+    pub sample_loop: Option<Range<usize>>, // first loop of the smpl chunk, in frames
+}
+
+/// Reads a PCM (8, 16, 24 or 32-bit) or floating-point (32 or 64-bit) WAV file
+/// The root key and first loop of a `smpl` chunk are read too, loops outside the
+/// samples are ignored.
+pub fn read(path: &Path) -> Result<Recording> {
+    let bytes = fs::read(path).context(format!("Failed to read WAV file: {}", path.display()))?;
+    parse(&bytes).context(format!("Failed to parse WAV file: {}", path.display()))
+}
+
+fn parse(bytes: &[u8]) -> Result<Recording> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        bail!("Not a RIFF/WAVE file");
+    }
+
+    // Chunks follow the RIFF header, each padded to an even size
+    let mut format = None;
+    let mut data = None;
+    let mut sampler = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let id = &bytes[offset..offset + 4];
+        let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into()?) as usize;
+        let body = &bytes[offset + 8..(offset + 8 + size).min(bytes.len())];
+        match id {
+            b"fmt " => format = Some(body),
+            b"data" => data = Some(body),
+            b"smpl" => sampler = Some(body),
+            _ => {}
+        }
+        offset += 8 + size + size % 2;
+    }
+    let Some(format) = format.filter(|format| format.len() >= 16) else {
+        bail!("Missing fmt chunk");
+    };
+    let Some(data) = data else {
+        bail!("Missing data chunk");
+    };
+
+    let field = |at: usize| u16::from_le_bytes([format[at], format[at + 1]]);
+    let mut code = field(0);
+    let channels = field(2) as usize;
+    let sample_rate = u32::from_le_bytes(format[4..8].try_into()?);
+    let block_align = field(12) as usize;
+    let bits = field(14);
+    if code == FORMAT_EXTENSIBLE && format.len() >= 26 {
+        code = field(24);
+    }
+    if channels == 0 {
+        bail!("The file has no channels");
+    }
+    if sample_rate == 0 {
+        bail!("The file has a sample rate of 0 Hz");
+    }
+    // Samples sit in whole bytes, left-justified when they don't fill them (20 bits in 3 bytes)
+    let width = bits.div_ceil(8) as usize;
+    if block_align < channels * width {
+        bail!(
+            "The file has a block align of {} bytes per frame, too small for {} channels of {} bytes",
+            block_align,
+            channels,
+            width
+        );
+    }
+
+    let decode: fn(&[u8]) -> f64 = match (code, width) {
+        (FORMAT_PCM, 1) => |b| (b[0] as f64 - 128.0) / 128.0,
+        (FORMAT_PCM, 2) => |b| i16::from_le_bytes([b[0], b[1]]) as f64 / 32768.0,
+        (FORMAT_PCM, 3) => |b| (i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8) as f64 / 8388608.0,
+        (FORMAT_PCM, 4) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64 / 2147483648.0,
+        (FORMAT_FLOAT, 4) if bits == 32 => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+        (FORMAT_FLOAT, 8) if bits == 64 => {
+            |b| f64::from_le_bytes(b[0..8].try_into().unwrap_or_default())
+        }
+        _ => bail!(
+            "Unsupported sample format (format {}, {} bits), use 8 to 32-bit PCM or 32/64-bit float",
+            code,
+            bits
+        ),
+    };
+
+    // Frames are `block_align` bytes apart, padding after the channels is skipped and the
+    // channels are averaged
+    let samples: Vec<f64> = data
+        .chunks_exact(block_align)
+        .map(|frame| {
+            frame[..channels * width]
+                .chunks_exact(width)
+                .map(decode)
+                .sum::<f64>()
+                / channels as f64
+        })
+        .collect();
+
+    let (root_key, sample_loop) = match sampler.filter(|body| body.len() >= SAMPLER_HEADER) {
+        Some(body) => {
+            let word = |at: usize| {
+                u32::from_le_bytes([body[at], body[at + 1], body[at + 2], body[at + 3]])
+            };
+            // The pitch fraction is a fraction of a semitone up, over 2^32
+            let root_key = word(12) as f64 + word(16) as f64 / 2_f64.powi(32);
+            // Loop ends are inclusive
+            let sample_loop = (word(28) > 0 && body.len() >= SAMPLER_HEADER + SAMPLER_LOOP)
+                .then(|| word(SAMPLER_HEADER + 8) as usize..word(SAMPLER_HEADER + 12) as usize + 1)
+                .filter(|range| range.start < range.end && range.end <= samples.len());
+            (Some(root_key), sample_loop)
+        }
+        None => (None, None),
+    };
+    Ok(Recording {
+        samples,
+        sample_rate,
+        root_key,
+        sample_loop,
+    })
+}
+
 pub fn write(filename: &str, samples: &[i16], sample_rate: u32) -> Result<()> {
     // Ensure output directory exists
     if let Some(parent) = Path::new(filename).parent() {
@@ -14,7 +147,11 @@ pub fn write(filename: &str, samples: &[i16], sample_rate: u32) -> Result<()> {
 
     let mut file =
         File::create(filename).context(format!("Failed to create WAV file: {}", filename))?;
+    write_to(&mut file, samples, sample_rate)
+}
 
+/// Writes a WAV stream to any writer, such as stdout
+pub fn write_to<W: Write>(file: &mut W, samples: &[i16], sample_rate: u32) -> Result<()> {
     // Audio format parameters
     let num_channels: u16 = 1; // Mono
     let bits_per_sample: u16 = 16; // 16-bit PCM
@@ -63,6 +200,7 @@ pub fn write(filename: &str, samples: &[i16], sample_rate: u32) -> Result<()> {
         file.write_all(&sample.to_le_bytes())
             .context("Failed to write PCM sample data")?;
     }
+    file.flush().context("Failed to flush WAV data")?;
 
     Ok(())
 }
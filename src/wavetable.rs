@@ -0,0 +1,226 @@
+use crate::adsr::ADSRConfig;
+use crate::errors::OrchestratorError;
+use crate::validation::validate_frame_size;
+use rayon::prelude::*;
+use serde::Deserialize;
+use std::f64::consts::TAU;
+use std::sync::Arc;
+
+// Samples in one cycle of a table, a power of two
+const TABLE_SIZE: usize = 2048;
+// Harmonics in the richest table, the most a table of this size can hold
+const MAX_HARMONICS: usize = TABLE_SIZE / 2;
+
+/// One cycle of a waveform as band-limited tables, one per octave
+/// Each table keeps half the harmonics of the one before it, so every note can play the
+/// richest table whose harmonics all stay below the Nyquist frequency.
+#[derive(Debug)]
+pub struct Wavetable {
+    levels: Vec<Vec<f64>>, // `levels[k]` keeps the first `MAX_HARMONICS >> k` harmonics
+}
+
+impl Wavetable {
+    /// Builds the tables from a waveform given as a function of the phase, from 0.0 to 1.0
+    pub fn new(wave: impl Fn(f64) -> f64) -> Self {
+        // Fourier transform of the cycle, bin `k` holds harmonic `k` and bin
+        // `TABLE_SIZE - k` its mirror image
+        let mut real: Vec<f64> = (0..TABLE_SIZE)
+            .map(|index| wave(index as f64 / TABLE_SIZE as f64))
+            .collect();
+        let mut imaginary = vec![0.0; TABLE_SIZE];
+        fft(&mut real, &mut imaginary);
+
+        let mut levels = Vec::new();
+        let mut kept = MAX_HARMONICS;
+        while kept > 0 {
+            // Harmonics above `kept` are dropped from both halves of the spectrum
+            let mut level_real = real.clone();
+            let mut level_imaginary = imaginary.clone();
+            for bin in kept + 1..TABLE_SIZE - kept {
+                level_real[bin] = 0.0;
+                level_imaginary[bin] = 0.0;
+            }
+            // The inverse transform is the forward one on the complex conjugate
+            for value in level_imaginary.iter_mut() {
+                *value = -*value;
+            }
+            fft(&mut level_real, &mut level_imaginary);
+            levels.push(
+                level_real
+                    .iter()
+                    .map(|value| value / TABLE_SIZE as f64)
+                    .collect(),
+            );
+            kept /= 2;
+        }
+        Wavetable { levels }
+    }
+
+    /// The richest level whose harmonics stay below Nyquist at this frequency
+    pub fn level(&self, frequency: f64, sample_rate: u32) -> usize {
+        let nyquist = sample_rate as f64 / 2.0;
+        (0..self.levels.len())
+            .find(|&level| (MAX_HARMONICS >> level) as f64 * frequency < nyquist)
+            .unwrap_or(self.levels.len() - 1)
+    }
+
+    /// Value of a level at a phase between 0.0 and 1.0, interpolated between its samples
+    pub fn value(&self, level: usize, phase: f64) -> f64 {
+        let table = &self.levels[level];
+        let position = phase * TABLE_SIZE as f64;
+        let index = position as usize % TABLE_SIZE;
+        let next = (index + 1) % TABLE_SIZE;
+        let fraction = position.fract();
+        table[index] + (table[next] - table[index]) * fraction
+    }
+}
+
+/// Frames of a wavetable file, each one cycle played from band-limited tables
+/// A single-cycle file is one frame, a multi-frame file is cut into frames of equal size.
+#[derive(Debug)]
+pub struct Frames {
+    frames: Vec<Wavetable>,
+}
+
+impl Frames {
+    /// Cuts samples into frames of `frame_size`, leftover samples at the end are ignored
+    pub fn from_samples(samples: &[f64], frame_size: usize) -> Result<Self, OrchestratorError> {
+        validate_frame_size(frame_size)?;
+        if samples.len() < frame_size {
+            return Err(OrchestratorError::InvalidWavetable(format!(
+                "the file has {} samples, fewer than one frame of {}",
+                samples.len(),
+                frame_size
+            )));
+        }
+        let frames = samples
+            .par_chunks_exact(frame_size)
+            .map(|frame| Wavetable::new(|phase| interpolate(frame, phase)))
+            .collect();
+        Ok(Frames { frames })
+    }
+
+    pub fn count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Table level to play at a frequency, the same for every frame
+    pub fn level(&self, frequency: f64, sample_rate: u32) -> usize {
+        self.frames[0].level(frequency, sample_rate)
+    }
+
+    /// Value at a position between 0.0 (first frame) and 1.0 (last frame), crossfading
+    /// between the two nearest frames
+    pub fn value(&self, level: usize, position: f64, phase: f64) -> f64 {
+        let last = self.frames.len() - 1;
+        let place = position.clamp(0.0, 1.0) * last as f64;
+        let frame = (place as usize).min(last.saturating_sub(1));
+        let current = self.frames[frame].value(level, phase);
+        if last == 0 {
+            return current;
+        }
+        let next = self.frames[frame + 1].value(level, phase);
+        current + (next - current) * (place - frame as f64)
+    }
+}
+
+/// How the position across the frames moves while a note plays
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct Morph {
+    #[serde(default)]
+    pub position: f64, // 0.0 is the first frame and 1.0 the last
+    pub envelope: Option<PositionEnvelope>,
+    pub lfo: Option<Lfo>,
+}
+
+/// Moves the position with an ADSR shape for each note
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct PositionEnvelope {
+    #[serde(flatten)]
+    pub adsr: ADSRConfig,
+    #[serde(default = "default_amount")]
+    pub amount: f64, // position moved at the envelope's peak, negative values move it back
+}
+
+/// Sweeps the position back and forth
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Lfo {
+    pub rate: f64,  // in Hz
+    pub depth: f64, // position moved either way
+}
+
+fn default_amount() -> f64 {
+    1.0
+}
+
+/// A wavetable ready to play, its frames shared by every note
+#[derive(Debug, Clone)]
+pub struct WavetableWave {
+    frames: Arc<Frames>,
+    morph: Morph,
+}
+
+impl WavetableWave {
+    pub fn new(frames: Frames, morph: Morph) -> Self {
+        WavetableWave {
+            frames: Arc::new(frames),
+            morph,
+        }
+    }
+
+    pub fn frames(&self) -> &Frames {
+        &self.frames
+    }
+
+    pub fn morph(&self) -> &Morph {
+        &self.morph
+    }
+}
+
+// Value of a frame at a phase, interpolated between its samples and wrapping at the end
+fn interpolate(frame: &[f64], phase: f64) -> f64 {
+    let position = phase * frame.len() as f64;
+    let index = position as usize % frame.len();
+    let next = (index + 1) % frame.len();
+    frame[index] + (frame[next] - frame[index]) * position.fract()
+}
+
+// In-place radix-2 fast Fourier transform, the length must be a power of two
+fn fft(real: &mut [f64], imaginary: &mut [f64]) {
+    let size = real.len();
+
+    // Reorder into bit-reversed index order
+    let mut reversed = 0;
+    for index in 1..size {
+        let mut bit = size >> 1;
+        while reversed & bit != 0 {
+            reversed ^= bit;
+            bit >>= 1;
+        }
+        reversed |= bit;
+        if index < reversed {
+            real.swap(index, reversed);
+            imaginary.swap(index, reversed);
+        }
+    }
+
+    // Combine transforms of doubling length
+    let mut length = 2;
+    while length <= size {
+        let angle = -TAU / length as f64;
+        for start in (0..size).step_by(length) {
+            for offset in 0..length / 2 {
+                let (sin, cos) = (angle * offset as f64).sin_cos();
+                let even = start + offset;
+                let odd = even + length / 2;
+                let odd_real = real[odd] * cos - imaginary[odd] * sin;
+                let odd_imaginary = real[odd] * sin + imaginary[odd] * cos;
+                real[odd] = real[even] - odd_real;
+                imaginary[odd] = imaginary[even] - odd_imaginary;
+                real[even] += odd_real;
+                imaginary[even] += odd_imaginary;
+            }
+        }
+        length *= 2;
+    }
+}